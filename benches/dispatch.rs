@@ -0,0 +1,56 @@
+extern crate mr_cool_nes;
+
+use std::time::{Duration, Instant};
+
+use mr_cool_nes::core::cpu::CPU;
+use mr_cool_nes::core::error::EmuError;
+use mr_cool_nes::core::mapper::TestMapper;
+use mr_cool_nes::core::memory::RAM;
+use mr_cool_nes::core::ppu::PPU;
+use mr_cool_nes::core::rom::Rom;
+
+// Measures raw instruction throughput of the OPCODES table against the old
+// decode match by running the instruction test suite, which exercises every
+// official and unofficial opcode. Needs the match around:
+//   cargo bench --bench dispatch --features match-dispatch
+const INSTRUCTIONS: u32 = 20_000_000;
+const RUNS: u32 = 5;
+
+fn bench(name: &str, step: fn(&mut CPU) -> Result<u8, EmuError>) -> Duration {
+    let rom_path = "tests/roms/cpu_instructions/all_instrs.nes".to_owned();
+    let mut best = None;
+
+    for run in 0..RUNS {
+        let rom = Rom::load(&rom_path).unwrap();
+        let mut cpu = CPU::new(PPU::new(), RAM::new(), Box::new(TestMapper::new(rom)));
+        cpu.reset();
+
+        let start = Instant::now();
+        for _ in 0..INSTRUCTIONS {
+            step(&mut cpu).unwrap();
+        }
+        let elapsed = start.elapsed();
+
+        println!("{} run {}: {:?}", name, run, elapsed);
+        if best.is_none_or(|b| elapsed < b) {
+            best = Some(elapsed);
+        }
+    }
+
+    let best = best.unwrap();
+    println!("{} best: {:?}, {:.1} million instructions per second",
+             name, best, INSTRUCTIONS as f64 / best.as_secs_f64() / 1e6);
+    best
+}
+
+fn main() {
+    // Interleaved so neither gets a warmer machine
+    let mut table = Duration::MAX;
+    let mut matched = Duration::MAX;
+    for _ in 0..2 {
+        matched = matched.min(bench("match", CPU::step_match));
+        table = table.min(bench("table", CPU::step));
+    }
+
+    println!("table/match: {:.3}", table.as_secs_f64() / matched.as_secs_f64());
+}
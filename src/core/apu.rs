@@ -0,0 +1,139 @@
+// Length counter values loaded by the top five bits of $4003, $4007, $400B
+// and $400F
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30
+];
+
+// Frame counter steps in CPU cycles. The last step of each sequence clocks
+// the length counters a second time and, in 4-step mode, raises the IRQ.
+const FOUR_STEP: [u32; 4] = [7457, 14913, 22371, 29829];
+const FIVE_STEP: [u32; 4] = [7457, 14913, 22371, 37281];
+const FOUR_STEP_PERIOD: u32 = 29830;
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+// Audio processing unit. Sound generation isn't implemented yet, but the
+// registers, length counters and frame counter behave like the real thing
+// so $4015 reads and the frame IRQ are correct.
+#[derive(Clone)]
+pub struct APU {
+    pub regs: [u8; 0x20],
+    pub cycles: u64,
+    length_counters: [u8; 4],
+    enabled: u8,
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool
+}
+
+impl APU {
+    pub fn new() -> APU {
+        info!("Creating an APU...");
+        APU {
+            regs: [0; 0x20],
+            cycles: 0,
+            length_counters: [0; 4],
+            enabled: 0,
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false
+        }
+    }
+
+    // Runs once per CPU cycle
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        self.frame_cycle += 1;
+
+        let (steps, period) = if self.five_step {
+            (FIVE_STEP, FIVE_STEP_PERIOD)
+        } else {
+            (FOUR_STEP, FOUR_STEP_PERIOD)
+        };
+
+        if self.frame_cycle == steps[1] || self.frame_cycle == steps[3] {
+            self.clock_length_counters();
+        }
+        if self.frame_cycle == steps[3] && !self.five_step && !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+        if self.frame_cycle >= period {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_length_counters(&mut self) {
+        for channel in 0..4 {
+            // Bit 5 of the first register halts the counter, bit 7 for the triangle
+            let halt = if channel == 2 { 0x80 } else { 0x20 };
+            if self.regs[channel * 4] & halt == 0 && self.length_counters[channel] > 0 {
+                self.length_counters[channel] -= 1;
+            }
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    // $4015. Bits 0-3 report which length counters are running, bit 6 the
+    // frame IRQ, which reading acknowledges. The DMC isn't implemented, so
+    // bits 4 and 7 stay clear.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    // $4015 without acknowledging the frame IRQ
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        for channel in 0..4 {
+            if self.length_counters[channel] > 0 {
+                status |= 1 << channel;
+            }
+        }
+        if self.frame_irq {
+            status |= 0x40;
+        }
+        status
+    }
+
+    // $4000-$4013, $4015, $4017 and the test registers at $4018-$401F
+    pub fn write(&mut self, addr: u16, val: u8) {
+        let reg = (addr & 0x1F) as usize;
+        self.regs[reg] = val;
+
+        match addr {
+            0x4003 | 0x4007 | 0x400B | 0x400F => {
+                let channel = reg / 4;
+                if self.enabled & (1 << channel) != 0 {
+                    self.length_counters[channel] = LENGTH_TABLE[(val >> 3) as usize];
+                }
+            },
+            0x4015 => {
+                self.enabled = val & 0x0F;
+                for channel in 0..4 {
+                    if self.enabled & (1 << channel) == 0 {
+                        self.length_counters[channel] = 0;
+                    }
+                }
+            },
+            // Switching to 5-step mode clocks the length counters right away
+            0x4017 => {
+                self.five_step = val & 0x80 != 0;
+                self.irq_inhibit = val & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_length_counters();
+                }
+            },
+            _ => ()
+        }
+    }
+}
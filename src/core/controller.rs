@@ -0,0 +1,61 @@
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+// Standard controller. While strobe is high the shift register keeps
+// reloading the buttons, once it goes low every read shifts out one button
+// in the order A, B, Select, Start, Up, Down, Left, Right and then ones.
+#[derive(Clone, Copy)]
+pub struct Controller {
+    pub buttons: u8,
+    shift: u8,
+    strobe: bool
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller {
+            buttons: 0,
+            shift: 0,
+            strobe: false
+        }
+    }
+
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        if pressed {
+            self.buttons |= button;
+        } else {
+            self.buttons &= !button;
+        }
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.strobe = val & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    // The bit the next read returns
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 0x01
+        } else {
+            self.shift & 0x01
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let bit = self.shift & 0x01;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
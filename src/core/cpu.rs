@@ -1,11 +1,15 @@
 use std::fmt;
+use std::io::Write;
+use core::error::EmuError;
 use core::mapper::Mapper;
 use core::memory::{CPUMemoryMap, Memory, RAM};
 use core::ppu::PPU;
+use core::trace;
 
-const NMI_VECTOR: u16 = 0xFFFA;
-const RESET_VECTOR: u16 = 0xFFFC;
-const BRK_VECTOR: u16 = 0xFFFE;
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const BRK_VECTOR: u16 = 0xFFFE;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
 
 pub const F_CARRY: u8 = 0x01;
 pub const F_ZERO: u8 = 0x02;
@@ -15,34 +19,421 @@ pub const F_BREAK: u8 = 0x10;
 pub const F_OVERFLOW: u8 = 0x40;
 pub const F_NEGATIVE: u8 = 0x80;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+    Relative
+}
+
+impl Mode {
+    // Length of the instruction including the opcode
+    pub const fn bytes(self) -> u8 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 1,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 3,
+            _ => 2
+        }
+    }
+}
+
+// Everything known about an opcode. Cycles don't include the branch
+// penalties, page_cross_cycles is what reads pay for crossing a page.
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+    pub bytes: u8,
+    pub cycles: u8,
+    pub page_cross_cycles: u8,
+    pub official: bool,
+    execute: fn(&mut CPU)
+}
+
+// Builds the addressing mode an instruction works on and runs it
+macro_rules! operand {
+    ($cpu:ident, Accumulator, $instr:ident) => { $cpu.$instr(AccumulatorAddressingMode) };
+    ($cpu:ident, Immediate, $instr:ident) => { $cpu.$instr(ImmediateAddressingMode) };
+    ($cpu:ident, ZeroPage, $instr:ident) => {{ let mode = $cpu.zero_page_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, ZeroPageX, $instr:ident) => {{ let mode = $cpu.zero_page_x_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, ZeroPageY, $instr:ident) => {{ let mode = $cpu.zero_page_y_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, Absolute, $instr:ident) => {{ let mode = $cpu.absolute_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, AbsoluteX, $instr:ident) => {{ let mode = $cpu.absolute_x_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, AbsoluteY, $instr:ident) => {{ let mode = $cpu.absolute_y_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, IndexedIndirect, $instr:ident) => {{ let mode = $cpu.indexed_indirect_addressing_mode(); $cpu.$instr(mode) }};
+    ($cpu:ident, IndirectIndexed, $instr:ident) => {{ let mode = $cpu.indirect_indexed_addressing_mode(); $cpu.$instr(mode) }};
+}
+
+// op!(MNEMONIC, Mode, cycles, page cross cycles, instruction). Unofficial
+// opcodes are marked with a *. Instructions written with () read their own
+// operands, like jumps and branches.
+macro_rules! op {
+    (@ $name:ident, $official:expr, $mode:ident, $cycles:expr, $page:expr, $instr:ident()) => {
+        Opcode {
+            mnemonic: stringify!($name),
+            mode: Mode::$mode,
+            bytes: Mode::$mode.bytes(),
+            cycles: $cycles,
+            page_cross_cycles: $page,
+            official: $official,
+            execute: |cpu| cpu.$instr()
+        }
+    };
+    (@ $name:ident, $official:expr, $mode:ident, $cycles:expr, $page:expr, $instr:ident) => {
+        Opcode {
+            mnemonic: stringify!($name),
+            mode: Mode::$mode,
+            bytes: Mode::$mode.bytes(),
+            cycles: $cycles,
+            page_cross_cycles: $page,
+            official: $official,
+            execute: |cpu| operand!(cpu, $mode, $instr)
+        }
+    };
+    (* $name:ident, $($rest:tt)*) => { op!(@ $name, false, $($rest)*) };
+    ($name:ident, $($rest:tt)*) => { op!(@ $name, true, $($rest)*) };
+}
+
+pub static OPCODES: [Opcode; 256] = [
+    /* 0x00 */ op!(BRK, Implied, 7, 0, brk()),
+    /* 0x01 */ op!(ORA, IndexedIndirect, 6, 0, ora),
+    /* 0x02 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x03 */ op!(*SLO, IndexedIndirect, 8, 0, slo),
+    /* 0x04 */ op!(*NOP, ZeroPage, 3, 0, nop_read),
+    /* 0x05 */ op!(ORA, ZeroPage, 3, 0, ora),
+    /* 0x06 */ op!(ASL, ZeroPage, 5, 0, asl),
+    /* 0x07 */ op!(*SLO, ZeroPage, 5, 0, slo),
+    /* 0x08 */ op!(PHP, Implied, 3, 0, php()),
+    /* 0x09 */ op!(ORA, Immediate, 2, 0, ora),
+    /* 0x0A */ op!(ASL, Accumulator, 2, 0, asl),
+    /* 0x0B */ op!(*ANC, Immediate, 2, 0, anc),
+    /* 0x0C */ op!(*NOP, Absolute, 4, 0, nop_read),
+    /* 0x0D */ op!(ORA, Absolute, 4, 0, ora),
+    /* 0x0E */ op!(ASL, Absolute, 6, 0, asl),
+    /* 0x0F */ op!(*SLO, Absolute, 6, 0, slo),
+    /* 0x10 */ op!(BPL, Relative, 2, 0, bpl()),
+    /* 0x11 */ op!(ORA, IndirectIndexed, 5, 1, ora),
+    /* 0x12 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x13 */ op!(*SLO, IndirectIndexed, 8, 0, slo),
+    /* 0x14 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0x15 */ op!(ORA, ZeroPageX, 4, 0, ora),
+    /* 0x16 */ op!(ASL, ZeroPageX, 6, 0, asl),
+    /* 0x17 */ op!(*SLO, ZeroPageX, 6, 0, slo),
+    /* 0x18 */ op!(CLC, Implied, 2, 0, clc()),
+    /* 0x19 */ op!(ORA, AbsoluteY, 4, 1, ora),
+    /* 0x1A */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0x1B */ op!(*SLO, AbsoluteY, 7, 0, slo),
+    /* 0x1C */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0x1D */ op!(ORA, AbsoluteX, 4, 1, ora),
+    /* 0x1E */ op!(ASL, AbsoluteX, 7, 0, asl),
+    /* 0x1F */ op!(*SLO, AbsoluteX, 7, 0, slo),
+    /* 0x20 */ op!(JSR, Absolute, 6, 0, jsr()),
+    /* 0x21 */ op!(AND, IndexedIndirect, 6, 0, and),
+    /* 0x22 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x23 */ op!(*RLA, IndexedIndirect, 8, 0, rla),
+    /* 0x24 */ op!(BIT, ZeroPage, 3, 0, bit),
+    /* 0x25 */ op!(AND, ZeroPage, 3, 0, and),
+    /* 0x26 */ op!(ROL, ZeroPage, 5, 0, rol),
+    /* 0x27 */ op!(*RLA, ZeroPage, 5, 0, rla),
+    /* 0x28 */ op!(PLP, Implied, 4, 0, plp()),
+    /* 0x29 */ op!(AND, Immediate, 2, 0, and),
+    /* 0x2A */ op!(ROL, Accumulator, 2, 0, rol),
+    /* 0x2B */ op!(*ANC, Immediate, 2, 0, anc),
+    /* 0x2C */ op!(BIT, Absolute, 4, 0, bit),
+    /* 0x2D */ op!(AND, Absolute, 4, 0, and),
+    /* 0x2E */ op!(ROL, Absolute, 6, 0, rol),
+    /* 0x2F */ op!(*RLA, Absolute, 6, 0, rla),
+    /* 0x30 */ op!(BMI, Relative, 2, 0, bmi()),
+    /* 0x31 */ op!(AND, IndirectIndexed, 5, 1, and),
+    /* 0x32 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x33 */ op!(*RLA, IndirectIndexed, 8, 0, rla),
+    /* 0x34 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0x35 */ op!(AND, ZeroPageX, 4, 0, and),
+    /* 0x36 */ op!(ROL, ZeroPageX, 6, 0, rol),
+    /* 0x37 */ op!(*RLA, ZeroPageX, 6, 0, rla),
+    /* 0x38 */ op!(SEC, Implied, 2, 0, sec()),
+    /* 0x39 */ op!(AND, AbsoluteY, 4, 1, and),
+    /* 0x3A */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0x3B */ op!(*RLA, AbsoluteY, 7, 0, rla),
+    /* 0x3C */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0x3D */ op!(AND, AbsoluteX, 4, 1, and),
+    /* 0x3E */ op!(ROL, AbsoluteX, 7, 0, rol),
+    /* 0x3F */ op!(*RLA, AbsoluteX, 7, 0, rla),
+    /* 0x40 */ op!(RTI, Implied, 6, 0, rti()),
+    /* 0x41 */ op!(EOR, IndexedIndirect, 6, 0, eor),
+    /* 0x42 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x43 */ op!(*SRE, IndexedIndirect, 8, 0, sre),
+    /* 0x44 */ op!(*NOP, ZeroPage, 3, 0, nop_read),
+    /* 0x45 */ op!(EOR, ZeroPage, 3, 0, eor),
+    /* 0x46 */ op!(LSR, ZeroPage, 5, 0, lsr),
+    /* 0x47 */ op!(*SRE, ZeroPage, 5, 0, sre),
+    /* 0x48 */ op!(PHA, Implied, 3, 0, pha()),
+    /* 0x49 */ op!(EOR, Immediate, 2, 0, eor),
+    /* 0x4A */ op!(LSR, Accumulator, 2, 0, lsr),
+    /* 0x4B */ op!(*ALR, Immediate, 2, 0, alr),
+    /* 0x4C */ op!(JMP, Absolute, 3, 0, jmp()),
+    /* 0x4D */ op!(EOR, Absolute, 4, 0, eor),
+    /* 0x4E */ op!(LSR, Absolute, 6, 0, lsr),
+    /* 0x4F */ op!(*SRE, Absolute, 6, 0, sre),
+    /* 0x50 */ op!(BVC, Relative, 2, 0, bvc()),
+    /* 0x51 */ op!(EOR, IndirectIndexed, 5, 1, eor),
+    /* 0x52 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x53 */ op!(*SRE, IndirectIndexed, 8, 0, sre),
+    /* 0x54 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0x55 */ op!(EOR, ZeroPageX, 4, 0, eor),
+    /* 0x56 */ op!(LSR, ZeroPageX, 6, 0, lsr),
+    /* 0x57 */ op!(*SRE, ZeroPageX, 6, 0, sre),
+    /* 0x58 */ op!(CLI, Implied, 2, 0, cli()),
+    /* 0x59 */ op!(EOR, AbsoluteY, 4, 1, eor),
+    /* 0x5A */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0x5B */ op!(*SRE, AbsoluteY, 7, 0, sre),
+    /* 0x5C */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0x5D */ op!(EOR, AbsoluteX, 4, 1, eor),
+    /* 0x5E */ op!(LSR, AbsoluteX, 7, 0, lsr),
+    /* 0x5F */ op!(*SRE, AbsoluteX, 7, 0, sre),
+    /* 0x60 */ op!(RTS, Implied, 6, 0, rts()),
+    /* 0x61 */ op!(ADC, IndexedIndirect, 6, 0, adc),
+    /* 0x62 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x63 */ op!(*RRA, IndexedIndirect, 8, 0, rra),
+    /* 0x64 */ op!(*NOP, ZeroPage, 3, 0, nop_read),
+    /* 0x65 */ op!(ADC, ZeroPage, 3, 0, adc),
+    /* 0x66 */ op!(ROR, ZeroPage, 5, 0, ror),
+    /* 0x67 */ op!(*RRA, ZeroPage, 5, 0, rra),
+    /* 0x68 */ op!(PLA, Implied, 4, 0, pla()),
+    /* 0x69 */ op!(ADC, Immediate, 2, 0, adc),
+    /* 0x6A */ op!(ROR, Accumulator, 2, 0, ror),
+    /* 0x6B */ op!(*ARR, Immediate, 2, 0, arr),
+    /* 0x6C */ op!(JMP, Indirect, 5, 0, jmp_indirect()),
+    /* 0x6D */ op!(ADC, Absolute, 4, 0, adc),
+    /* 0x6E */ op!(ROR, Absolute, 6, 0, ror),
+    /* 0x6F */ op!(*RRA, Absolute, 6, 0, rra),
+    /* 0x70 */ op!(BVS, Relative, 2, 0, bvs()),
+    /* 0x71 */ op!(ADC, IndirectIndexed, 5, 1, adc),
+    /* 0x72 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x73 */ op!(*RRA, IndirectIndexed, 8, 0, rra),
+    /* 0x74 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0x75 */ op!(ADC, ZeroPageX, 4, 0, adc),
+    /* 0x76 */ op!(ROR, ZeroPageX, 6, 0, ror),
+    /* 0x77 */ op!(*RRA, ZeroPageX, 6, 0, rra),
+    /* 0x78 */ op!(SEI, Implied, 2, 0, sei()),
+    /* 0x79 */ op!(ADC, AbsoluteY, 4, 1, adc),
+    /* 0x7A */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0x7B */ op!(*RRA, AbsoluteY, 7, 0, rra),
+    /* 0x7C */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0x7D */ op!(ADC, AbsoluteX, 4, 1, adc),
+    /* 0x7E */ op!(ROR, AbsoluteX, 7, 0, ror),
+    /* 0x7F */ op!(*RRA, AbsoluteX, 7, 0, rra),
+    /* 0x80 */ op!(*NOP, Immediate, 2, 0, nop_read),
+    /* 0x81 */ op!(STA, IndexedIndirect, 6, 0, sta),
+    /* 0x82 */ op!(*NOP, Immediate, 2, 0, nop_read),
+    /* 0x83 */ op!(*SAX, IndexedIndirect, 6, 0, sax),
+    /* 0x84 */ op!(STY, ZeroPage, 3, 0, sty),
+    /* 0x85 */ op!(STA, ZeroPage, 3, 0, sta),
+    /* 0x86 */ op!(STX, ZeroPage, 3, 0, stx),
+    /* 0x87 */ op!(*SAX, ZeroPage, 3, 0, sax),
+    /* 0x88 */ op!(DEY, Implied, 2, 0, dey()),
+    /* 0x89 */ op!(*NOP, Immediate, 2, 0, nop_read),
+    /* 0x8A */ op!(TXA, Implied, 2, 0, txa()),
+    /* 0x8B */ op!(*XAA, Immediate, 2, 0, xaa),
+    /* 0x8C */ op!(STY, Absolute, 4, 0, sty),
+    /* 0x8D */ op!(STA, Absolute, 4, 0, sta),
+    /* 0x8E */ op!(STX, Absolute, 4, 0, stx),
+    /* 0x8F */ op!(*SAX, Absolute, 4, 0, sax),
+    /* 0x90 */ op!(BCC, Relative, 2, 0, bcc()),
+    /* 0x91 */ op!(STA, IndirectIndexed, 6, 0, sta),
+    /* 0x92 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0x93 */ op!(*SHA, IndirectIndexed, 6, 0, sha),
+    /* 0x94 */ op!(STY, ZeroPageX, 4, 0, sty),
+    /* 0x95 */ op!(STA, ZeroPageX, 4, 0, sta),
+    /* 0x96 */ op!(STX, ZeroPageY, 4, 0, stx),
+    /* 0x97 */ op!(*SAX, ZeroPageY, 4, 0, sax),
+    /* 0x98 */ op!(TYA, Implied, 2, 0, tya()),
+    /* 0x99 */ op!(STA, AbsoluteY, 5, 0, sta),
+    /* 0x9A */ op!(TXS, Implied, 2, 0, txs()),
+    /* 0x9B */ op!(*TAS, AbsoluteY, 5, 0, tas),
+    /* 0x9C */ op!(*SHY, AbsoluteX, 5, 0, shy),
+    /* 0x9D */ op!(STA, AbsoluteX, 5, 0, sta),
+    /* 0x9E */ op!(*SHX, AbsoluteY, 5, 0, shx),
+    /* 0x9F */ op!(*SHA, AbsoluteY, 5, 0, sha),
+    /* 0xA0 */ op!(LDY, Immediate, 2, 0, ldy),
+    /* 0xA1 */ op!(LDA, IndexedIndirect, 6, 0, lda),
+    /* 0xA2 */ op!(LDX, Immediate, 2, 0, ldx),
+    /* 0xA3 */ op!(*LAX, IndexedIndirect, 6, 0, lax),
+    /* 0xA4 */ op!(LDY, ZeroPage, 3, 0, ldy),
+    /* 0xA5 */ op!(LDA, ZeroPage, 3, 0, lda),
+    /* 0xA6 */ op!(LDX, ZeroPage, 3, 0, ldx),
+    /* 0xA7 */ op!(*LAX, ZeroPage, 3, 0, lax),
+    /* 0xA8 */ op!(TAY, Implied, 2, 0, tay()),
+    /* 0xA9 */ op!(LDA, Immediate, 2, 0, lda),
+    /* 0xAA */ op!(TAX, Implied, 2, 0, tax()),
+    /* 0xAB */ op!(*LXA, Immediate, 2, 0, lxa),
+    /* 0xAC */ op!(LDY, Absolute, 4, 0, ldy),
+    /* 0xAD */ op!(LDA, Absolute, 4, 0, lda),
+    /* 0xAE */ op!(LDX, Absolute, 4, 0, ldx),
+    /* 0xAF */ op!(*LAX, Absolute, 4, 0, lax),
+    /* 0xB0 */ op!(BCS, Relative, 2, 0, bcs()),
+    /* 0xB1 */ op!(LDA, IndirectIndexed, 5, 1, lda),
+    /* 0xB2 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0xB3 */ op!(*LAX, IndirectIndexed, 5, 1, lax),
+    /* 0xB4 */ op!(LDY, ZeroPageX, 4, 0, ldy),
+    /* 0xB5 */ op!(LDA, ZeroPageX, 4, 0, lda),
+    /* 0xB6 */ op!(LDX, ZeroPageY, 4, 0, ldx),
+    /* 0xB7 */ op!(*LAX, ZeroPageY, 4, 0, lax),
+    /* 0xB8 */ op!(CLV, Implied, 2, 0, clv()),
+    /* 0xB9 */ op!(LDA, AbsoluteY, 4, 1, lda),
+    /* 0xBA */ op!(TSX, Implied, 2, 0, tsx()),
+    /* 0xBB */ op!(*LAS, AbsoluteY, 4, 1, las),
+    /* 0xBC */ op!(LDY, AbsoluteX, 4, 1, ldy),
+    /* 0xBD */ op!(LDA, AbsoluteX, 4, 1, lda),
+    /* 0xBE */ op!(LDX, AbsoluteY, 4, 1, ldx),
+    /* 0xBF */ op!(*LAX, AbsoluteY, 4, 1, lax),
+    /* 0xC0 */ op!(CPY, Immediate, 2, 0, cpy),
+    /* 0xC1 */ op!(CMP, IndexedIndirect, 6, 0, cmp),
+    /* 0xC2 */ op!(*NOP, Immediate, 2, 0, nop_read),
+    /* 0xC3 */ op!(*DCP, IndexedIndirect, 8, 0, dcp),
+    /* 0xC4 */ op!(CPY, ZeroPage, 3, 0, cpy),
+    /* 0xC5 */ op!(CMP, ZeroPage, 3, 0, cmp),
+    /* 0xC6 */ op!(DEC, ZeroPage, 5, 0, dec),
+    /* 0xC7 */ op!(*DCP, ZeroPage, 5, 0, dcp),
+    /* 0xC8 */ op!(INY, Implied, 2, 0, iny()),
+    /* 0xC9 */ op!(CMP, Immediate, 2, 0, cmp),
+    /* 0xCA */ op!(DEX, Implied, 2, 0, dex()),
+    /* 0xCB */ op!(*AXS, Immediate, 2, 0, axs),
+    /* 0xCC */ op!(CPY, Absolute, 4, 0, cpy),
+    /* 0xCD */ op!(CMP, Absolute, 4, 0, cmp),
+    /* 0xCE */ op!(DEC, Absolute, 6, 0, dec),
+    /* 0xCF */ op!(*DCP, Absolute, 6, 0, dcp),
+    /* 0xD0 */ op!(BNE, Relative, 2, 0, bne()),
+    /* 0xD1 */ op!(CMP, IndirectIndexed, 5, 1, cmp),
+    /* 0xD2 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0xD3 */ op!(*DCP, IndirectIndexed, 8, 0, dcp),
+    /* 0xD4 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0xD5 */ op!(CMP, ZeroPageX, 4, 0, cmp),
+    /* 0xD6 */ op!(DEC, ZeroPageX, 6, 0, dec),
+    /* 0xD7 */ op!(*DCP, ZeroPageX, 6, 0, dcp),
+    /* 0xD8 */ op!(CLD, Implied, 2, 0, cld()),
+    /* 0xD9 */ op!(CMP, AbsoluteY, 4, 1, cmp),
+    /* 0xDA */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0xDB */ op!(*DCP, AbsoluteY, 7, 0, dcp),
+    /* 0xDC */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0xDD */ op!(CMP, AbsoluteX, 4, 1, cmp),
+    /* 0xDE */ op!(DEC, AbsoluteX, 7, 0, dec),
+    /* 0xDF */ op!(*DCP, AbsoluteX, 7, 0, dcp),
+    /* 0xE0 */ op!(CPX, Immediate, 2, 0, cpx),
+    /* 0xE1 */ op!(SBC, IndexedIndirect, 6, 0, sbc),
+    /* 0xE2 */ op!(*NOP, Immediate, 2, 0, nop_read),
+    /* 0xE3 */ op!(*ISB, IndexedIndirect, 8, 0, isc),
+    /* 0xE4 */ op!(CPX, ZeroPage, 3, 0, cpx),
+    /* 0xE5 */ op!(SBC, ZeroPage, 3, 0, sbc),
+    /* 0xE6 */ op!(INC, ZeroPage, 5, 0, inc),
+    /* 0xE7 */ op!(*ISB, ZeroPage, 5, 0, isc),
+    /* 0xE8 */ op!(INX, Implied, 2, 0, inx()),
+    /* 0xE9 */ op!(SBC, Immediate, 2, 0, sbc),
+    /* 0xEA */ op!(NOP, Implied, 2, 0, noop()),
+    /* 0xEB */ op!(*SBC, Immediate, 2, 0, sbc),
+    /* 0xEC */ op!(CPX, Absolute, 4, 0, cpx),
+    /* 0xED */ op!(SBC, Absolute, 4, 0, sbc),
+    /* 0xEE */ op!(INC, Absolute, 6, 0, inc),
+    /* 0xEF */ op!(*ISB, Absolute, 6, 0, isc),
+    /* 0xF0 */ op!(BEQ, Relative, 2, 0, beq()),
+    /* 0xF1 */ op!(SBC, IndirectIndexed, 5, 1, sbc),
+    /* 0xF2 */ op!(*KIL, Implied, 2, 0, hlt()),
+    /* 0xF3 */ op!(*ISB, IndirectIndexed, 8, 0, isc),
+    /* 0xF4 */ op!(*NOP, ZeroPageX, 4, 0, nop_read),
+    /* 0xF5 */ op!(SBC, ZeroPageX, 4, 0, sbc),
+    /* 0xF6 */ op!(INC, ZeroPageX, 6, 0, inc),
+    /* 0xF7 */ op!(*ISB, ZeroPageX, 6, 0, isc),
+    /* 0xF8 */ op!(SED, Implied, 2, 0, sed()),
+    /* 0xF9 */ op!(SBC, AbsoluteY, 4, 1, sbc),
+    /* 0xFA */ op!(*NOP, Implied, 2, 0, noop()),
+    /* 0xFB */ op!(*ISB, AbsoluteY, 7, 0, isc),
+    /* 0xFC */ op!(*NOP, AbsoluteX, 4, 1, nop_read),
+    /* 0xFD */ op!(SBC, AbsoluteX, 4, 1, sbc),
+    /* 0xFE */ op!(INC, AbsoluteX, 7, 0, inc),
+    /* 0xFF */ op!(*ISB, AbsoluteX, 7, 0, isc),
+];
+
 pub trait AddressingMode {
     fn load(&self, cpu: &mut CPU) -> u8;
     fn store(&self, cpu: &mut CPU, val: u8);
+
+    // Read-modify-write instructions go through these, memory operands
+    // make extra bus accesses that registers don't
+    fn load_rmw(&self, cpu: &mut CPU) -> u8 {
+        self.load(cpu)
+    }
+
+    fn store_rmw(&self, cpu: &mut CPU, _old: u8, new: u8) {
+        self.store(cpu, new);
+    }
 }
 
 pub struct AccumulatorAddressingMode;
 impl AddressingMode for AccumulatorAddressingMode {
-    fn load(&self, cpu: &mut CPU) -> u8 { cpu.regs.a }
+    fn load(&self, cpu: &mut CPU) -> u8 {
+        cpu.dummy_read_pc();
+        cpu.regs.a
+    }
+
     fn store(&self, cpu: &mut CPU, val: u8) { cpu.regs.a = val; }
 }
 
 pub struct ImmediateAddressingMode;
 impl AddressingMode for ImmediateAddressingMode {
     fn load(&self, cpu: &mut CPU) -> u8 { cpu.load_byte_increment_pc() }
-    fn store(&self, cpu: &mut CPU, val: u8) { panic!("Attempted write with immediate addressing mode") }
+    fn store(&self, cpu: &mut CPU, val: u8) {
+        let regs = cpu.regs;
+        cpu.fault(EmuError::IllegalWrite { val, regs });
+    }
+}
+
+// Indexed modes keep the address from before the carry into the high byte
+// was fixed up. The CPU reads from it while it adds the carry, which reads
+// only do when a page is crossed and writes always do.
+pub struct MemoryAddressingMode{val: u16, uncarried: Option<u16>}
+impl MemoryAddressingMode {
+    fn dummy_read(&self, cpu: &mut CPU, always: bool) {
+        if let Some(addr) = self.uncarried {
+            if always || addr != self.val {
+                cpu.load_byte(addr);
+            }
+        }
+    }
 }
 
-pub struct MemoryAddressingMode{val: u16}
 impl AddressingMode for MemoryAddressingMode {
     fn load(&self, cpu: &mut CPU) -> u8 {
+        self.dummy_read(cpu, false);
         cpu.load_byte(self.val)
     }
 
     fn store(&self, cpu: &mut CPU, val: u8) {
+        self.dummy_read(cpu, true);
         cpu.store_byte(self.val, val);
     }
+
+    fn load_rmw(&self, cpu: &mut CPU) -> u8 {
+        self.dummy_read(cpu, true);
+        cpu.load_byte(self.val)
+    }
+
+    // The unmodified value is written back while the ALU works on it
+    fn store_rmw(&self, cpu: &mut CPU, old: u8, new: u8) {
+        cpu.store_byte(self.val, old);
+        cpu.store_byte(self.val, new);
+    }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct Registers {
     pub a: u8,
     pub x: u8,
@@ -72,69 +463,271 @@ impl fmt::Display for Registers {
     }
 }
 
-pub struct CPU<'a> {
+pub struct CPU {
     pub regs: Registers,
-    pub mem_map: CPUMemoryMap<'a>
+    pub mem_map: CPUMemoryMap,
+    pub cycles: u64,
+    page_crossed: bool,
+    extra_cycles: u8,
+    dma_page: Option<u8>,
+    stall: u16,
+    // The OAM DMA read and write cycles left, where the next read comes
+    // from and the byte on its way to $2004
+    dma_cycles: u16,
+    dma_addr: u16,
+    dma_latch: u8,
+    nmi_line: bool,
+    nmi_pending: bool,
+    // Edge detector for the PPU's NMI output, which is polled once per cycle
+    ppu_nmi: bool,
+    nmi_detected: bool,
+    nmi_polled: bool,
+    irq_line: bool,
+    irq_inhibit: bool,
+    jammed: bool,
+    fault: Option<EmuError>,
+    trace: Option<Box<dyn Write + Send>>,
+    tracing: bool
+}
+
+// Save states clone the whole machine. The trace sink and any pending
+// error stay with the original.
+impl Clone for CPU {
+    fn clone(&self) -> CPU {
+        CPU {
+            regs: self.regs,
+            mem_map: self.mem_map.clone(),
+            cycles: self.cycles,
+            page_crossed: self.page_crossed,
+            extra_cycles: self.extra_cycles,
+            dma_page: self.dma_page,
+            stall: self.stall,
+            dma_cycles: self.dma_cycles,
+            dma_addr: self.dma_addr,
+            dma_latch: self.dma_latch,
+            nmi_line: self.nmi_line,
+            nmi_pending: self.nmi_pending,
+            ppu_nmi: self.ppu_nmi,
+            nmi_detected: self.nmi_detected,
+            nmi_polled: self.nmi_polled,
+            irq_line: self.irq_line,
+            irq_inhibit: self.irq_inhibit,
+            jammed: self.jammed,
+            fault: None,
+            trace: None,
+            tracing: false
+        }
+    }
 }
 
-impl<'a> CPU<'a> {
-    pub fn new(ppu: &mut PPU, ram: RAM, mapper: Box<Mapper>) -> CPU {
+impl CPU {
+    pub fn new(ppu: PPU, ram: RAM, mapper: Box<Mapper>) -> CPU {
         info!("Creating a CPU...");
         CPU {
             regs: Registers::new(),
-            mem_map: CPUMemoryMap::new(ppu, ram, mapper)
+            mem_map: CPUMemoryMap::new(ppu, ram, mapper),
+            cycles: 0,
+            page_crossed: false,
+            extra_cycles: 0,
+            dma_page: None,
+            stall: 0,
+            dma_cycles: 0,
+            dma_addr: 0,
+            dma_latch: 0,
+            nmi_line: false,
+            nmi_pending: false,
+            ppu_nmi: false,
+            nmi_detected: false,
+            nmi_polled: false,
+            irq_line: false,
+            irq_inhibit: true,
+            jammed: false,
+            fault: None,
+            trace: None,
+            tracing: false
+        }
+    }
+
+    // NMI is edge triggered, only a low to high transition requests an interrupt
+    pub fn set_nmi(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = level;
+    }
+
+    // IRQ is level triggered and keeps firing for as long as the line is held
+    pub fn set_irq(&mut self, level: bool) {
+        self.irq_line = level;
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    pub fn irq_line(&self) -> bool {
+        self.irq_line
+    }
+
+    // A jammed CPU stops fetching instructions until it's reset,
+    // PC is left pointing at the opcode that jammed it
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    // Errors can't be returned through the Memory trait or addressing modes,
+    // so they're stored here and handed out at the end of the step
+    pub fn fault(&mut self, error: EmuError) {
+        if self.fault.is_none() {
+            self.fault = Some(error);
         }
     }
 
-    fn dma(&mut self, high_byte: u8) {
-        let start = (high_byte as u16) << 8;
+    // Logs every instruction in the nestest format before it runs
+    pub fn trace_to(&mut self, sink: Box<dyn Write + Send>) {
+        self.trace = Some(sink);
+        self.tracing = true;
+    }
 
-        for addr in start..start + 256 {
-            let val = self.load_byte(addr);
-            self.store_byte(0x2004, val);
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracing && self.trace.is_some()
+    }
+
+    pub fn take_trace(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.tracing = false;
+        self.trace.take()
+    }
+
+    fn write_trace(&mut self) {
+        if let Some(mut sink) = self.trace.take() {
+            let line = trace::trace_line(self);
+            if let Err(e) = writeln!(sink, "{}", line) {
+                self.fault(EmuError::Io(e));
+            }
+            self.trace = Some(sink);
         }
     }
 
+    fn interrupt(&mut self, vector: u16) -> u8 {
+        self.dummy_read_pc();
+        self.dummy_read_pc();
+        let pc = self.regs.pc;
+        let p = self.regs.p;
+        self.push_word(pc);
+        self.push_byte((p & !F_BREAK) | 0x20);
+        self.set_flag(F_INTERRUPT, true);
+        self.irq_inhibit = true;
+        self.regs.pc = self.load_word(vector);
+        self.cycles += 7;
+        7
+    }
+
+    // Halts the CPU for the given number of cycles, the DMC uses this when it
+    // fetches sample bytes
+    pub fn stall(&mut self, cycles: u16) {
+        self.stall += cycles;
+    }
+
+    pub fn stall_cycles(&self) -> u16 {
+        self.stall
+    }
+
+    // Switching the console on. A, X and Y start at 0 and S at 0, which the
+    // reset sequence then takes down to $FD.
+    pub fn power_on(&mut self) {
+        self.regs = Registers::new();
+        self.regs.s = 0x00;
+        self.cycles = 0;
+        self.mem_map.power_on();
+        self.reset();
+    }
+
+    // The reset button. A, X, Y and RAM keep their values, S goes down by 3
+    // and interrupts are disabled.
     pub fn reset(&mut self) {
+        self.mem_map.reset();
+
+        // Reset runs the interrupt sequence with writes turned into reads
+        self.dummy_read_pc();
+        self.dummy_read_pc();
+        for _ in 0..3 {
+            let addr = self.stack_pointer();
+            self.load_byte(addr);
+            self.regs.s = self.regs.s.wrapping_sub(1);
+        }
         self.regs.pc = self.load_word(RESET_VECTOR);
-        self.regs.s -= 3;
         self.regs.p |= 0x04;
+        self.irq_inhibit = true;
+        self.nmi_pending = false;
+        self.nmi_detected = false;
+        self.nmi_polled = false;
+        self.jammed = false;
+        self.dma_page = None;
+        self.stall = 0;
+        self.dma_cycles = 0;
+        self.cycles += 7;
+        self.mem_map.catch_up();
         info!("Regs after reset: {}", self.regs);
     }
 
     pub fn zero_page_addressing_mode(&mut self) -> MemoryAddressingMode {
-        MemoryAddressingMode{val: self.load_byte_increment_pc() as u16}
+        MemoryAddressingMode{val: self.load_byte_increment_pc() as u16, uncarried: None}
     }
 
     pub fn zero_page_x_addressing_mode(&mut self) -> MemoryAddressingMode {
-        MemoryAddressingMode{val: (self.load_byte_increment_pc() +
-        self.regs.x) as u16}
+        let x = self.regs.x;
+        let base = self.load_byte_increment_pc();
+        self.load_byte(base as u16);
+        MemoryAddressingMode{val: base.wrapping_add(x) as u16, uncarried: None}
+    }
+
+    pub fn zero_page_y_addressing_mode(&mut self) -> MemoryAddressingMode {
+        let y = self.regs.y;
+        let base = self.load_byte_increment_pc();
+        self.load_byte(base as u16);
+        MemoryAddressingMode{val: base.wrapping_add(y) as u16, uncarried: None}
     }
 
     pub fn absolute_addressing_mode(&mut self) -> MemoryAddressingMode {
-        MemoryAddressingMode {val: self.load_word_increment_pc() as u16}
+        MemoryAddressingMode {val: self.load_word_increment_pc() as u16, uncarried: None}
     }
 
     pub fn absolute_x_addressing_mode(&mut self) -> MemoryAddressingMode {
-        MemoryAddressingMode {val: self.load_word_increment_pc() + self.regs.x as u16}
+        let base = self.load_word_increment_pc();
+        let x = self.regs.x;
+        self.indexed_addressing_mode(base, x)
     }
 
     pub fn absolute_y_addressing_mode(&mut self) -> MemoryAddressingMode {
-        MemoryAddressingMode {val: self.load_word_increment_pc() + self.regs.y as u16}
+        let base = self.load_word_increment_pc();
+        let y = self.regs.y;
+        self.indexed_addressing_mode(base, y)
+    }
+
+    fn indexed_addressing_mode(&mut self, base: u16, index: u8) -> MemoryAddressingMode {
+        let val = base.wrapping_add(index as u16);
+        let uncarried = (base & 0xFF00) | (val & 0x00FF);
+        self.page_crossed = uncarried != val;
+        MemoryAddressingMode {val, uncarried: Some(uncarried)}
     }
 
     pub fn indexed_indirect_addressing_mode(&mut self) -> MemoryAddressingMode {
         let addr = self.load_byte_increment_pc();
         let x = self.regs.x;
-        let val = self.load_word_zeropage_wraparound(addr + x);
-        MemoryAddressingMode {val}
+        self.load_byte(addr as u16);
+        let val = self.load_word_zeropage_wraparound(addr.wrapping_add(x));
+        MemoryAddressingMode {val, uncarried: None}
     }
 
     pub fn indirect_indexed_addressing_mode(&mut self) -> MemoryAddressingMode {
         let addr = self.load_byte_increment_pc();
         let y = self.regs.y;
-        let val = self.load_word_zeropage_wraparound(addr) + y as u16;
-        MemoryAddressingMode {val}
+        let base = self.load_word_zeropage_wraparound(addr);
+        self.indexed_addressing_mode(base, y)
     }
     
     pub fn stack_pointer(&self) -> u16 {
@@ -144,7 +737,7 @@ impl<'a> CPU<'a> {
     pub fn push_byte(&mut self, val: u8) {
         let addr = self.stack_pointer();
         self.store_byte(addr, val);
-        self.regs.s -= 1;
+        self.regs.s = self.regs.s.wrapping_sub(1);
     }
 
     pub fn push_word(&mut self, val: u16) {
@@ -156,9 +749,9 @@ impl<'a> CPU<'a> {
     }
 
     pub fn pop_byte(&mut self) -> u8 {
+        self.regs.s = self.regs.s.wrapping_add(1);
         let addr = self.stack_pointer();
-        self.regs.s += 1;
-        self.load_byte(addr + 1)
+        self.load_byte(addr)
     }
 
     pub fn pop_word(&mut self) -> u16 {
@@ -168,162 +761,454 @@ impl<'a> CPU<'a> {
         ((hi as u16) << 8) | (low as u16)
     }
 
-    pub fn printable_stack(& mut self) -> String {
+    pub fn printable_stack(&self) -> String {
         let mut stack = "".to_owned();
         for i in self.regs.s..0xFF {
-            stack += &format!("{:X} ", self.load_byte(0x100 + i as u16));
+            stack += &format!("{:X} ", self.peek(0x100 + i as u16));
         }
         stack
     }
 
+    // Instructions without an operand still read the byte after the opcode
+    pub fn dummy_read_pc(&mut self) {
+        let pc = self.regs.pc;
+        self.load_byte(pc);
+    }
+
+    fn dummy_read_stack(&mut self) {
+        let addr = self.stack_pointer();
+        self.load_byte(addr);
+    }
+
+    fn read_modify_write<M, F>(&mut self, mode: &M, op: F) -> u8
+        where M: AddressingMode, F: FnOnce(&mut Self, u8) -> u8 {
+        let val = mode.load_rmw(self);
+        let result = op(self, val);
+        mode.store_rmw(self, val, result);
+        result
+    }
+
     pub fn load_byte_increment_pc(&mut self) -> u8 {
         let pc = self.regs.pc;
         let val = self.load_byte(pc);
-        self.regs.pc += 1;
+        self.regs.pc = pc.wrapping_add(1);
+        val
+    }
+
+    // Opcode fetch, which bus hooks see as Access::Fetch
+    fn fetch_byte_increment_pc(&mut self) -> u8 {
+        let pc = self.regs.pc;
+        self.begin_cycle();
+        let val = self.mem_map.fetch_byte(pc);
+        self.end_cycle();
+        self.regs.pc = pc.wrapping_add(1);
         val
     }
 
+    // Interrupts are polled on the second to last cycle of an instruction,
+    // so only an NMI detected by the end of the previous cycle gets taken
+    // when the current one turns out to be the last
+    fn begin_cycle(&mut self) {
+        self.nmi_polled = self.nmi_detected;
+        self.mem_map.tick();
+    }
+
+    // The NMI output is sampled after the access, so a $2002 read right as
+    // vblank starts can still suppress it. The PPU only has to be caught up
+    // while it's able to raise one.
+    fn end_cycle(&mut self) {
+        if self.mem_map.ppu.nmi_enabled() {
+            self.mem_map.catch_up();
+        }
+        let level = self.mem_map.ppu.nmi();
+        if level && !self.ppu_nmi {
+            self.nmi_detected = true;
+        }
+        self.ppu_nmi = level;
+    }
+
     pub fn load_word_increment_pc(&mut self) -> u16 {
         let pc = self.regs.pc;
         let val = self.load_word(pc);
-        self.regs.pc += 2;
+        self.regs.pc = pc.wrapping_add(2);
         val
     }
 
-    pub fn step(&mut self) {
-        let next = self.load_byte_increment_pc();
+    // Executes one instruction, or enters a pending interrupt handler,
+    // and returns the number of cycles it took
+    pub fn step(&mut self) -> Result<u8, EmuError> {
+        self.step_with(CPU::decode)
+    }
+
+    // step with the decode match OPCODES replaced, for the dispatch bench
+    #[cfg(feature = "match-dispatch")]
+    pub fn step_match(&mut self) -> Result<u8, EmuError> {
+        self.step_with(CPU::decode_match)
+    }
+
+    fn step_with<D: Fn(&mut CPU, u8)>(&mut self, decode: D) -> Result<u8, EmuError> {
+        let start = self.mem_map.scheduler.cpu_cycles();
+        let result = self.execute(decode);
+
+        // Every bus access already moved the clock, cycles without one
+        // still have to pass
+        if let Ok(cycles) = result {
+            let accessed = self.mem_map.scheduler.cpu_cycles() - start;
+            for _ in accessed..cycles as u64 {
+                self.begin_cycle();
+                self.end_cycle();
+            }
+        }
+        self.mem_map.catch_up();
+
+        if self.nmi_polled {
+            self.nmi_pending = true;
+            self.nmi_detected = false;
+            self.nmi_polled = false;
+        }
+
+        result
+    }
+
+    fn execute<D: Fn(&mut CPU, u8)>(&mut self, decode: D) -> Result<u8, EmuError> {
+        if self.jammed {
+            return Ok(0);
+        }
+
+        // Stalled cycles are handed out one at a time so the PPU keeps
+        // running alongside. The last 512 of an OAM DMA alternate between
+        // reading the page and writing $2004.
+        if self.stall > 0 {
+            self.stall -= 1;
+            self.cycles += 1;
+            if self.dma_cycles > 0 && self.stall < self.dma_cycles {
+                self.dma_cycles -= 1;
+                if self.dma_cycles & 1 == 1 {
+                    let addr = self.dma_addr;
+                    self.dma_latch = self.load_byte(addr);
+                    self.dma_addr = addr.wrapping_add(1);
+                } else {
+                    let val = self.dma_latch;
+                    self.store_byte(0x2004, val);
+                }
+            }
+            return Ok(1);
+        }
+
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            return Ok(self.interrupt(NMI_VECTOR));
+        }
+
+        if (self.irq_line || self.mem_map.irq()) && !self.irq_inhibit {
+            return Ok(self.interrupt(IRQ_VECTOR));
+        }
+
+        if self.tracing {
+            self.write_trace();
+        }
+
+        let interrupt_flag = self.get_flag(F_INTERRUPT);
+        let next = self.fetch_byte_increment_pc();
+
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        decode(self, next);
+
+        let opcode = &OPCODES[next as usize];
+        let mut cycles = opcode.cycles + self.extra_cycles;
+        if self.page_crossed {
+            cycles += opcode.page_cross_cycles;
+        }
+
+        // Interrupts are polled before the last cycle of an instruction, so
+        // CLI, SEI and PLP only change the outcome after the next instruction
+        self.irq_inhibit = match next {
+            0x28 | 0x58 | 0x78 => interrupt_flag,
+            _ => self.get_flag(F_INTERRUPT)
+        };
+
+        self.cycles += cycles as u64;
 
-            //println!("Opcode: {:X}", next);
-            //println!("PC: {:X}\n", self.regs.pc);
-            //`println!("Stack: {}\n", self.printable_stack());
-        
-        self.decode(next);
+        // OAM DMA copies a page from anywhere on the CPU bus to $2004. The
+        // CPU is halted for 513 cycles, plus one more to align with the DMA
+        // unit when the transfer starts on an odd cycle.
+        if let Some(page) = self.dma_page.take() {
+            self.stall += 513 + (self.cycles & 1) as u16;
+            self.dma_cycles = 512;
+            self.dma_addr = (page as u16) << 8;
+        }
+
+        match self.fault.take() {
+            Some(error) => Err(error),
+            None => Ok(cycles)
+        }
     }
 
     pub fn decode(&mut self, opcode: u8) {
+        (OPCODES[opcode as usize].execute)(self);
+    }
+
+    #[cfg(feature = "match-dispatch")]
+    fn decode_match(&mut self, opcode: u8) {
         match opcode {
             0x00 => self.brk(),
             0x01 => {let mode = self.indexed_indirect_addressing_mode(); self.ora(mode);},
             0x02 => self.hlt(),
+            0x03 => {let mode = self.indexed_indirect_addressing_mode(); self.slo(mode);},
+            0x04 => {let mode = self.zero_page_addressing_mode(); self.nop_read(mode);},
             0x05 => {let mode = self.zero_page_addressing_mode(); self.ora(mode);},
             0x06 => {let mode = self.zero_page_addressing_mode(); self.asl(mode);},
+            0x07 => {let mode = self.zero_page_addressing_mode(); self.slo(mode);},
             0x08 => self.php(),
             0x09 => self.ora(ImmediateAddressingMode),
             0x0A => self.asl(AccumulatorAddressingMode),
-            0x0B => self.noop(), // Illegal opcode - ANC ImmediateAddressingMode
+            0x0B => self.anc(ImmediateAddressingMode),
+            0x0C => {let mode = self.absolute_addressing_mode(); self.nop_read(mode);},
             0x0D => {let mode = self.absolute_addressing_mode(); self.ora(mode);},
+            0x0E => {let mode = self.absolute_addressing_mode(); self.asl(mode);},
+            0x0F => {let mode = self.absolute_addressing_mode(); self.slo(mode);},
             0x10 => self.bpl(),
             0x11 => {let mode = self.indirect_indexed_addressing_mode(); self.ora(mode);},
+            0x12 => self.hlt(),
+            0x13 => {let mode = self.indirect_indexed_addressing_mode(); self.slo(mode);},
+            0x14 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
             0x15 => {let mode = self.zero_page_x_addressing_mode(); self.ora(mode);},
-            0x17 => self.noop(), // Illegal opcode - SLO
+            0x16 => {let mode = self.zero_page_x_addressing_mode(); self.asl(mode);},
+            0x17 => {let mode = self.zero_page_x_addressing_mode(); self.slo(mode);},
             0x18 => self.clc(),
             0x19 => {let mode = self.absolute_y_addressing_mode(); self.ora(mode);},
             0x1A => self.noop(),
-            0x1C => self.noop(),
+            0x1B => {let mode = self.absolute_y_addressing_mode(); self.slo(mode);},
+            0x1C => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
             0x1D => {let mode = self.absolute_x_addressing_mode(); self.ora(mode);},
+            0x1E => {let mode = self.absolute_x_addressing_mode(); self.asl(mode);},
+            0x1F => {let mode = self.absolute_x_addressing_mode(); self.slo(mode);},
             0x20 => self.jsr(),
             0x21 => {let mode = self.indexed_indirect_addressing_mode(); self.and(mode);},
+            0x22 => self.hlt(),
+            0x23 => {let mode = self.indexed_indirect_addressing_mode(); self.rla(mode);},
             0x24 => {let mode = self.zero_page_addressing_mode(); self.bit(mode);},
+            0x25 => {let mode = self.zero_page_addressing_mode(); self.and(mode);},
             0x26 => {let mode = self.zero_page_addressing_mode(); self.rol(mode);},
+            0x27 => {let mode = self.zero_page_addressing_mode(); self.rla(mode);},
             0x28 => self.plp(),
             0x29 => self.and(ImmediateAddressingMode),
             0x2A => self.rol(AccumulatorAddressingMode),
+            0x2B => self.anc(ImmediateAddressingMode),
             0x2C => {let mode = self.absolute_addressing_mode(); self.bit(mode);},
+            0x2D => {let mode = self.absolute_addressing_mode(); self.and(mode);},
+            0x2E => {let mode = self.absolute_addressing_mode(); self.rol(mode);},
+            0x2F => {let mode = self.absolute_addressing_mode(); self.rla(mode);},
             0x30 => self.bmi(),
+            0x31 => {let mode = self.indirect_indexed_addressing_mode(); self.and(mode);},
             0x32 => self.hlt(),
+            0x33 => {let mode = self.indirect_indexed_addressing_mode(); self.rla(mode);},
+            0x34 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
+            0x35 => {let mode = self.zero_page_x_addressing_mode(); self.and(mode);},
+            0x36 => {let mode = self.zero_page_x_addressing_mode(); self.rol(mode);},
+            0x37 => {let mode = self.zero_page_x_addressing_mode(); self.rla(mode);},
             0x38 => self.sec(),
+            0x39 => {let mode = self.absolute_y_addressing_mode(); self.and(mode);},
             0x3A => self.noop(),
+            0x3B => {let mode = self.absolute_y_addressing_mode(); self.rla(mode);},
+            0x3C => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
+            0x3D => {let mode = self.absolute_x_addressing_mode(); self.and(mode);},
+            0x3E => {let mode = self.absolute_x_addressing_mode(); self.rol(mode);},
+            0x3F => {let mode = self.absolute_x_addressing_mode(); self.rla(mode);},
             0x40 => self.rti(),
             0x41 => {let mode = self.indexed_indirect_addressing_mode(); self.eor(mode);},
-            0x44 => self.noop(),
+            0x42 => self.hlt(),
+            0x43 => {let mode = self.indexed_indirect_addressing_mode(); self.sre(mode);},
+            0x44 => {let mode = self.zero_page_addressing_mode(); self.nop_read(mode);},
             0x45 => {let mode = self.zero_page_addressing_mode(); self.eor(mode);},
-            0x46 => {let mode = self.zero_page_addressing_mode(); self.lsr(mode)},
+            0x46 => {let mode = self.zero_page_addressing_mode(); self.lsr(mode);},
+            0x47 => {let mode = self.zero_page_addressing_mode(); self.sre(mode);},
             0x48 => self.pha(),
             0x49 => self.eor(ImmediateAddressingMode),
             0x4A => self.lsr(AccumulatorAddressingMode),
+            0x4B => self.alr(ImmediateAddressingMode),
             0x4C => self.jmp(),
             0x4D => {let mode = self.absolute_addressing_mode(); self.eor(mode);},
-            0x54 => self.noop(),
+            0x4E => {let mode = self.absolute_addressing_mode(); self.lsr(mode);},
+            0x4F => {let mode = self.absolute_addressing_mode(); self.sre(mode);},
+            0x50 => self.bvc(),
+            0x51 => {let mode = self.indirect_indexed_addressing_mode(); self.eor(mode);},
+            0x52 => self.hlt(),
+            0x53 => {let mode = self.indirect_indexed_addressing_mode(); self.sre(mode);},
+            0x54 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
+            0x55 => {let mode = self.zero_page_x_addressing_mode(); self.eor(mode);},
+            0x56 => {let mode = self.zero_page_x_addressing_mode(); self.lsr(mode);},
+            0x57 => {let mode = self.zero_page_x_addressing_mode(); self.sre(mode);},
             0x58 => self.cli(),
+            0x59 => {let mode = self.absolute_y_addressing_mode(); self.eor(mode);},
             0x5A => self.noop(),
-            0x5C => self.noop(),
+            0x5B => {let mode = self.absolute_y_addressing_mode(); self.sre(mode);},
+            0x5C => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
             0x5D => {let mode = self.absolute_x_addressing_mode(); self.eor(mode);},
+            0x5E => {let mode = self.absolute_x_addressing_mode(); self.lsr(mode);},
+            0x5F => {let mode = self.absolute_x_addressing_mode(); self.sre(mode);},
             0x60 => self.rts(),
+            0x61 => {let mode = self.indexed_indirect_addressing_mode(); self.adc(mode);},
+            0x62 => self.hlt(),
+            0x63 => {let mode = self.indexed_indirect_addressing_mode(); self.rra(mode);},
+            0x64 => {let mode = self.zero_page_addressing_mode(); self.nop_read(mode);},
+            0x65 => {let mode = self.zero_page_addressing_mode(); self.adc(mode);},
             0x66 => {let mode = self.zero_page_addressing_mode(); self.ror(mode);},
+            0x67 => {let mode = self.zero_page_addressing_mode(); self.rra(mode);},
             0x68 => self.pla(),
             0x69 => self.adc(ImmediateAddressingMode),
             0x6A => self.ror(AccumulatorAddressingMode),
-            0x6B => self.noop(), // Illegal opcode - ARR ImmediateAddressingMode
+            0x6B => self.arr(ImmediateAddressingMode),
             0x6C => self.jmp_indirect(),
+            0x6D => {let mode = self.absolute_addressing_mode(); self.adc(mode);},
+            0x6E => {let mode = self.absolute_addressing_mode(); self.ror(mode);},
+            0x6F => {let mode = self.absolute_addressing_mode(); self.rra(mode);},
             0x70 => self.bvs(),
+            0x71 => {let mode = self.indirect_indexed_addressing_mode(); self.adc(mode);},
+            0x72 => self.hlt(),
+            0x73 => {let mode = self.indirect_indexed_addressing_mode(); self.rra(mode);},
+            0x74 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
+            0x75 => {let mode = self.zero_page_x_addressing_mode(); self.adc(mode);},
+            0x76 => {let mode = self.zero_page_x_addressing_mode(); self.ror(mode);},
+            0x77 => {let mode = self.zero_page_x_addressing_mode(); self.rra(mode);},
             0x78 => self.sei(),
+            0x79 => {let mode = self.absolute_y_addressing_mode(); self.adc(mode);},
             0x7A => self.noop(),
-            0x7C => self.noop(),
-            0x80 => self.noop(),
+            0x7B => {let mode = self.absolute_y_addressing_mode(); self.rra(mode);},
+            0x7C => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
+            0x7D => {let mode = self.absolute_x_addressing_mode(); self.adc(mode);},
+            0x7E => {let mode = self.absolute_x_addressing_mode(); self.ror(mode);},
+            0x7F => {let mode = self.absolute_x_addressing_mode(); self.rra(mode);},
+            0x80 => self.nop_read(ImmediateAddressingMode),
             0x81 => {let mode = self.indexed_indirect_addressing_mode(); self.sta(mode);},
+            0x82 => self.nop_read(ImmediateAddressingMode),
+            0x83 => {let mode = self.indexed_indirect_addressing_mode(); self.sax(mode);},
             0x84 => {let mode = self.zero_page_addressing_mode(); self.sty(mode);},
             0x85 => {let mode = self.zero_page_addressing_mode(); self.sta(mode);},
             0x86 => {let mode = self.zero_page_addressing_mode(); self.stx(mode);},
+            0x87 => {let mode = self.zero_page_addressing_mode(); self.sax(mode);},
             0x88 => self.dey(),
+            0x89 => self.nop_read(ImmediateAddressingMode),
             0x8A => self.txa(),
+            0x8B => self.xaa(ImmediateAddressingMode),
             0x8C => {let mode = self.absolute_addressing_mode(); self.sty(mode);},
             0x8D => {let mode = self.absolute_addressing_mode(); self.sta(mode);},
             0x8E => {let mode = self.absolute_addressing_mode(); self.stx(mode);},
+            0x8F => {let mode = self.absolute_addressing_mode(); self.sax(mode);},
             0x90 => self.bcc(),
             0x91 => {let mode = self.indirect_indexed_addressing_mode(); self.sta(mode);},
+            0x92 => self.hlt(),
+            0x93 => {let mode = self.indirect_indexed_addressing_mode(); self.sha(mode);},
+            0x94 => {let mode = self.zero_page_x_addressing_mode(); self.sty(mode);},
             0x95 => {let mode = self.zero_page_x_addressing_mode(); self.sta(mode);},
+            0x96 => {let mode = self.zero_page_y_addressing_mode(); self.stx(mode);},
+            0x97 => {let mode = self.zero_page_y_addressing_mode(); self.sax(mode);},
             0x98 => self.tya(),
             0x99 => {let mode = self.absolute_y_addressing_mode(); self.sta(mode);},
             0x9A => self.txs(),
-            0x9D => {let mode = self.absolute_x_addressing_mode(); self.sta(mode)},
+            0x9B => {let mode = self.absolute_y_addressing_mode(); self.tas(mode);},
+            0x9C => {let mode = self.absolute_x_addressing_mode(); self.shy(mode);},
+            0x9D => {let mode = self.absolute_x_addressing_mode(); self.sta(mode);},
+            0x9E => {let mode = self.absolute_y_addressing_mode(); self.shx(mode);},
+            0x9F => {let mode = self.absolute_y_addressing_mode(); self.sha(mode);},
             0xA0 => self.ldy(ImmediateAddressingMode),
+            0xA1 => {let mode = self.indexed_indirect_addressing_mode(); self.lda(mode);},
             0xA2 => self.ldx(ImmediateAddressingMode),
+            0xA3 => {let mode = self.indexed_indirect_addressing_mode(); self.lax(mode);},
             0xA4 => {let mode = self.zero_page_addressing_mode(); self.ldy(mode);},
             0xA5 => {let mode = self.zero_page_addressing_mode(); self.lda(mode);},
             0xA6 => {let mode = self.zero_page_addressing_mode(); self.ldx(mode);},
+            0xA7 => {let mode = self.zero_page_addressing_mode(); self.lax(mode);},
             0xA8 => self.tay(),
             0xA9 => self.lda(ImmediateAddressingMode),
             0xAA => self.tax(),
+            0xAB => self.lxa(ImmediateAddressingMode),
             0xAC => {let mode = self.absolute_addressing_mode(); self.ldy(mode);},
             0xAD => {let mode = self.absolute_addressing_mode(); self.lda(mode);},
             0xAE => {let mode = self.absolute_addressing_mode(); self.ldx(mode);},
+            0xAF => {let mode = self.absolute_addressing_mode(); self.lax(mode);},
             0xB0 => self.bcs(),
             0xB1 => {let mode = self.indirect_indexed_addressing_mode(); self.lda(mode);},
+            0xB2 => self.hlt(),
+            0xB3 => {let mode = self.indirect_indexed_addressing_mode(); self.lax(mode);},
+            0xB4 => {let mode = self.zero_page_x_addressing_mode(); self.ldy(mode);},
             0xB5 => {let mode = self.zero_page_x_addressing_mode(); self.lda(mode);},
+            0xB6 => {let mode = self.zero_page_y_addressing_mode(); self.ldx(mode);},
+            0xB7 => {let mode = self.zero_page_y_addressing_mode(); self.lax(mode);},
             0xB8 => self.clv(),
             0xB9 => {let mode = self.absolute_y_addressing_mode(); self.lda(mode);},
             0xBA => self.tsx(),
+            0xBB => {let mode = self.absolute_y_addressing_mode(); self.las(mode);},
+            0xBC => {let mode = self.absolute_x_addressing_mode(); self.ldy(mode);},
             0xBD => {let mode = self.absolute_x_addressing_mode(); self.lda(mode);},
+            0xBE => {let mode = self.absolute_y_addressing_mode(); self.ldx(mode);},
+            0xBF => {let mode = self.absolute_y_addressing_mode(); self.lax(mode);},
             0xC0 => self.cpy(ImmediateAddressingMode),
+            0xC1 => {let mode = self.indexed_indirect_addressing_mode(); self.cmp(mode);},
+            0xC2 => self.nop_read(ImmediateAddressingMode),
+            0xC3 => {let mode = self.indexed_indirect_addressing_mode(); self.dcp(mode);},
             0xC4 => {let mode = self.zero_page_addressing_mode(); self.cpy(mode);},
-            0xCA => self.dex(),
+            0xC5 => {let mode = self.zero_page_addressing_mode(); self.cmp(mode);},
+            0xC6 => {let mode = self.zero_page_addressing_mode(); self.dec(mode);},
+            0xC7 => {let mode = self.zero_page_addressing_mode(); self.dcp(mode);},
             0xC8 => self.iny(),
             0xC9 => self.cmp(ImmediateAddressingMode),
+            0xCA => self.dex(),
+            0xCB => self.axs(ImmediateAddressingMode),
+            0xCC => {let mode = self.absolute_addressing_mode(); self.cpy(mode);},
+            0xCD => {let mode = self.absolute_addressing_mode(); self.cmp(mode);},
+            0xCE => {let mode = self.absolute_addressing_mode(); self.dec(mode);},
+            0xCF => {let mode = self.absolute_addressing_mode(); self.dcp(mode);},
             0xD0 => self.bne(),
+            0xD1 => {let mode = self.indirect_indexed_addressing_mode(); self.cmp(mode);},
+            0xD2 => self.hlt(),
+            0xD3 => {let mode = self.indirect_indexed_addressing_mode(); self.dcp(mode);},
+            0xD4 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
+            0xD5 => {let mode = self.zero_page_x_addressing_mode(); self.cmp(mode);},
+            0xD6 => {let mode = self.zero_page_x_addressing_mode(); self.dec(mode);},
+            0xD7 => {let mode = self.zero_page_x_addressing_mode(); self.dcp(mode);},
             0xD8 => self.cld(),
             0xD9 => {let mode = self.absolute_y_addressing_mode(); self.cmp(mode);},
             0xDA => self.noop(),
+            0xDB => {let mode = self.absolute_y_addressing_mode(); self.dcp(mode);},
+            0xDC => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
             0xDD => {let mode = self.absolute_x_addressing_mode(); self.cmp(mode);},
             0xDE => {let mode = self.absolute_x_addressing_mode(); self.dec(mode);},
+            0xDF => {let mode = self.absolute_x_addressing_mode(); self.dcp(mode);},
             0xE0 => self.cpx(ImmediateAddressingMode),
-            0xE3 => self.noop(), // Illegal opcode
+            0xE1 => {let mode = self.indexed_indirect_addressing_mode(); self.sbc(mode);},
+            0xE2 => self.nop_read(ImmediateAddressingMode),
+            0xE3 => {let mode = self.indexed_indirect_addressing_mode(); self.isc(mode);},
+            0xE4 => {let mode = self.zero_page_addressing_mode(); self.cpx(mode);},
+            0xE5 => {let mode = self.zero_page_addressing_mode(); self.sbc(mode);},
             0xE6 => {let mode = self.zero_page_addressing_mode(); self.inc(mode);},
+            0xE7 => {let mode = self.zero_page_addressing_mode(); self.isc(mode);},
             0xE8 => self.inx(),
             0xE9 => self.sbc(ImmediateAddressingMode),
             0xEA => self.noop(),
             0xEB => self.sbc(ImmediateAddressingMode),
+            0xEC => {let mode = self.absolute_addressing_mode(); self.cpx(mode);},
             0xED => {let mode = self.absolute_addressing_mode(); self.sbc(mode);},
+            0xEE => {let mode = self.absolute_addressing_mode(); self.inc(mode);},
+            0xEF => {let mode = self.absolute_addressing_mode(); self.isc(mode);},
             0xF0 => self.beq(),
             0xF1 => {let mode = self.indirect_indexed_addressing_mode(); self.sbc(mode);},
+            0xF2 => self.hlt(),
+            0xF3 => {let mode = self.indirect_indexed_addressing_mode(); self.isc(mode);},
+            0xF4 => {let mode = self.zero_page_x_addressing_mode(); self.nop_read(mode);},
+            0xF5 => {let mode = self.zero_page_x_addressing_mode(); self.sbc(mode);},
+            0xF6 => {let mode = self.zero_page_x_addressing_mode(); self.inc(mode);},
+            0xF7 => {let mode = self.zero_page_x_addressing_mode(); self.isc(mode);},
             0xF8 => self.sed(),
             0xF9 => {let mode = self.absolute_y_addressing_mode(); self.sbc(mode);},
             0xFA => self.noop(),
-            0xFB => self.noop(), // Illegal opcode - RMW AbsoluteYAddressingMode
-            0xFC => self.noop(), // Illegal opcode
+            0xFB => {let mode = self.absolute_y_addressing_mode(); self.isc(mode);},
+            0xFC => {let mode = self.absolute_x_addressing_mode(); self.nop_read(mode);},
+            0xFD => {let mode = self.absolute_x_addressing_mode(); self.sbc(mode);},
             0xFE => {let mode = self.absolute_x_addressing_mode(); self.inc(mode);},
-            0xFF => self.noop(), // Illegal opcode - ISC {adr} = INC {adr} + SBC {adr}
-            _ => panic!("Unimplemented opcode: {:X}\nRegisters on crash: {}", opcode, self.regs)
-        };
+            0xFF => {let mode = self.absolute_x_addressing_mode(); self.isc(mode);},
+        }
     }
 
     pub fn get_flag(&mut self, flag: u8) -> bool {
@@ -350,54 +1235,90 @@ impl<'a> CPU<'a> {
 
     pub fn branch(&mut self, condition: bool) {
         let offset = self.load_byte_increment_pc() as i8;
-        if(condition) {
-            self.regs.pc = (self.regs.pc as i32 + offset as i32) as u16;
+        if condition {
+            let pc = self.regs.pc;
+            self.regs.pc = (pc as i32 + offset as i32) as u16;
+
+            // One cycle for taking the branch, another if it lands on a
+            // different page. Both read from PC before the high byte is fixed.
+            self.extra_cycles += 1;
+            self.load_byte(pc);
+            if (pc & 0xFF00) != (self.regs.pc & 0xFF00) {
+                self.extra_cycles += 1;
+                self.load_byte((pc & 0xFF00) | (self.regs.pc & 0x00FF));
+            }
         }
     }
 
     pub fn compare<M: AddressingMode>(&mut self, register: u8, mode: M) {
         let val = mode.load(self);
-        let result = (register as i32 - val as i32);
+        self.compare_value(register, val);
+    }
+
+    fn compare_value(&mut self, register: u8, val: u8) {
         self.set_flag(F_CARRY, register >= val);
-        self.set_zn(result as u8);
+        self.set_zn(register.wrapping_sub(val));
     }
     
-    fn noop(&self) {}
+    fn noop(&mut self) {
+        self.dummy_read_pc();
+    }
 
+    // The break flag only exists in the copy of P pushed on the stack.
+    // An NMI arriving before the vector is fetched hijacks the BRK, which
+    // then runs the NMI handler with the break flag still pushed.
     fn brk(&mut self) {
+        self.load_byte_increment_pc();
         let pc = self.regs.pc;
         let p = self.regs.p;
-        self.push_word(pc + 1);
-        self.push_byte(p);
-        self.set_flag(F_BREAK, true);
-        self.regs.pc = self.load_word(BRK_VECTOR);
+        self.push_word(pc);
+        self.push_byte(p | F_BREAK | 0x20);
+        self.set_flag(F_INTERRUPT, true);
+
+        // An NMI that comes in before the status gets pushed takes over the
+        // vector fetch, B still ends up set on the stack
+        let vector = if self.nmi_polled {
+            self.nmi_polled = false;
+            self.nmi_detected = false;
+            NMI_VECTOR
+        } else {
+            BRK_VECTOR
+        };
+        self.regs.pc = self.load_word(vector);
     }
 
     fn sei(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_INTERRUPT, true);
     }
 
     fn sec(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_CARRY, true);
     }
 
     fn sed(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_DECIMAL, true);
     }
 
     fn cld(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_DECIMAL, false);
     }
 
     fn cli(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_INTERRUPT, false);
     }
 
     fn clc(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_CARRY, false);
     }
 
     fn clv(&mut self) {
+        self.dummy_read_pc();
         self.set_flag(F_OVERFLOW, false);
     }
 
@@ -411,10 +1332,13 @@ impl<'a> CPU<'a> {
         self.regs.pc = operand;
     }
 
+    // The 6502 never carries into the high byte of the pointer,
+    // so JMP ($10FF) reads from $10FF and $1000
     fn jmp_indirect(&mut self) {
         let addr = self.load_word_increment_pc();
-        let destination = self.load_word(addr);
-        self.regs.pc = destination;
+        let low = self.load_byte(addr);
+        let hi = self.load_byte((addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF));
+        self.regs.pc = ((hi as u16) << 8) | (low as u16);
     }
 
     fn and<M: AddressingMode>(&mut self, mode: M) {
@@ -428,7 +1352,9 @@ impl<'a> CPU<'a> {
     }
 
     fn hlt(&mut self) {
-        info!("Halt instruction executed, reset required");
+        self.regs.pc = self.regs.pc.wrapping_sub(1);
+        self.jammed = true;
+        info!("Halt instruction executed at {:X}, reset required", self.regs.pc);
     }
 
     pub fn sta<M: AddressingMode>(&mut self, mode: M) {
@@ -462,72 +1388,84 @@ impl<'a> CPU<'a> {
     }
 
     fn pha(&mut self) {
+        self.dummy_read_pc();
         let a = self.regs.a;
         self.push_byte(a);
     }
 
+    // The return address is pushed before the high byte of the target is
+    // read, so it points at the last byte of the JSR
     fn jsr(&mut self) {
-        let addr = self.load_word_increment_pc();
+        let low = self.load_byte_increment_pc();
+        self.dummy_read_stack();
         let pc = self.regs.pc;
-        self.push_word(pc - 1);
-        self.regs.pc = addr;
+        self.push_word(pc);
+        let hi = self.load_byte(pc);
+        self.regs.pc = ((hi as u16) << 8) | (low as u16);
     }
 
     fn tya(&mut self) {
+        self.dummy_read_pc();
         let y = self.regs.y;
         self.regs.a = self.set_zn(y);
     }
 
     fn txs(&mut self) {
-        let x = self.regs.x;
-        self.regs.s = self.set_zn(x);
+        self.dummy_read_pc();
+        self.regs.s = self.regs.x;
     }
 
     fn txa(&mut self) {
+        self.dummy_read_pc();
         let x = self.regs.x;
         self.regs.a = self.set_zn(x);
     }
 
     fn tax(&mut self) {
+        self.dummy_read_pc();
         let a = self.regs.a;
         self.regs.x = self.set_zn(a);
     }
 
     fn tay(&mut self) {
+        self.dummy_read_pc();
         let a = self.regs.a;
         self.regs.y = self.set_zn(a);
     }
     
     fn tsx(&mut self) {
+        self.dummy_read_pc();
         let s = self.regs.s;
         self.regs.x = self.set_zn(s);
     }
 
 
     fn inx(&mut self) {
+        self.dummy_read_pc();
         let x = self.regs.x;
         self.regs.x = self.set_zn(x.wrapping_add(1));
     }
 
     fn iny(&mut self) {
+        self.dummy_read_pc();
         let y = self.regs.y;
         self.regs.y = self.set_zn(y.wrapping_add(1));
     }
 
     fn dex(&mut self) {
+        self.dummy_read_pc();
         let x = self.regs.x;
         self.regs.x = self.set_zn(x.wrapping_sub(1));
     }
 
     fn dey(&mut self) {
+        self.dummy_read_pc();
         let y = self.regs.y;
         self.regs.y = self.set_zn(y.wrapping_sub(1));
     }
 
     fn dec<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
-        val = self.set_zn(val.wrapping_sub(1));
-        mode.store(self, val);
+        self.read_modify_write(&mode, |cpu, val| cpu.set_zn(val.wrapping_sub(1)));
     }
     
     fn bne(&mut self) {
@@ -554,6 +1492,11 @@ impl<'a> CPU<'a> {
         let flag = self.get_flag(F_OVERFLOW);
         self.branch(flag);
     }
+
+    fn bvc(&mut self) {
+        let flag = self.get_flag(F_OVERFLOW);
+        self.branch(!flag);
+    }
     
     fn bcc(&mut self) {
         let flag = self.get_flag(F_CARRY);
@@ -580,35 +1523,26 @@ impl<'a> CPU<'a> {
         self.compare(y, mode);
     }
 
+    // A - M - (1 - C) is the same as A + !M + C
     fn sbc<M: AddressingMode>(&mut self, mode: M) {
         let val = mode.load(self);
-        let a = self.regs.a;
-
-        let temp = a as i16 - val as i16 - (1 - (self.get_flag(F_CARRY) as i16));
-        self.set_flag(F_NEGATIVE, (temp >> 7) & 1 != 0);
-
-        // Not worth it to put this long condition into set_flag
-        if(((a ^ temp as u8) & 0x80) != 0 &&
-           ((a ^ val) & 0x80) != 0) {
-            self.set_flag(F_OVERFLOW, true);
-        } else {
-            self.set_flag(F_OVERFLOW, false);
-        }
-
-        self.set_flag(F_CARRY, temp < 0);
-        self.regs.a = temp as u8 & 0xff;
-        
+        self.add_with_carry(!val);
     }
 
     fn rti(&mut self) {
+        self.dummy_read_pc();
+        self.dummy_read_stack();
         let flags = self.pop_byte();
         self.set_flags(flags);
         self.regs.pc = self.pop_word();
     }
 
     fn rts(&mut self) {
+        self.dummy_read_pc();
+        self.dummy_read_stack();
         let pc = self.pop_word();
-        self.regs.pc = pc + 1;
+        self.load_byte(pc);
+        self.regs.pc = pc.wrapping_add(1);
     }
 
     fn bit<M: AddressingMode>(&mut self, mode: M) {
@@ -620,69 +1554,82 @@ impl<'a> CPU<'a> {
     }
 
     fn rol<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
+        self.read_modify_write(&mode, |cpu, val| cpu.rotate_left(val));
+    }
+
+    fn ror<M: AddressingMode>(&mut self, mode: M) {
+        self.read_modify_write(&mode, |cpu, val| cpu.rotate_right(val));
+    }
+
+    fn lsr<M: AddressingMode>(&mut self, mode: M) {
+        self.read_modify_write(&mode, |cpu, val| cpu.shift_right(val));
+    }
+
+    fn rotate_left(&mut self, mut val: u8) -> u8 {
         let carry = self.get_flag(F_CARRY);
         let new_carry = (val & 0x80) > 0;
-        val = val << 1;
-        if(carry) {
+        val <<= 1;
+        if carry {
             val |= 1;
         }
         self.set_flag(F_CARRY, new_carry);
-        val = self.set_zn(val);
-        mode.store(self, val);
+        self.set_zn(val)
     }
 
-    fn ror<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
+    fn rotate_right(&mut self, mut val: u8) -> u8 {
         let carry = self.get_flag(F_CARRY);
         self.set_flag(F_CARRY, val & 0x01 != 0);
-        val = val >> 1;
-        if(carry) {
+        val >>= 1;
+        if carry {
             val |= 0x80;
         }
-        val = self.set_zn(val);
-        mode.store(self, val);
+        self.set_zn(val)
     }
 
-    fn lsr<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
+    fn shift_left(&mut self, val: u8) -> u8 {
+        self.set_flag(F_CARRY, 0x80 & val != 0);
+        self.set_zn(val << 1)
+    }
+
+    fn shift_right(&mut self, val: u8) -> u8 {
         self.set_flag(F_CARRY, val & 0x01 != 0);
-        val = val >> 1;
-        val = self.set_zn(val);
-        mode.store(self, val);
+        self.set_zn(val >> 1)
     }
 
     pub fn inc<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
-        val = self.set_zn(val.wrapping_add(1));
-        mode.store(self, val);
+        self.read_modify_write(&mode, |cpu, val| cpu.set_zn(val.wrapping_add(1)));
     }
 
     fn pla(&mut self) {
+        self.dummy_read_pc();
+        self.dummy_read_stack();
         let val = self.pop_byte();
         self.regs.a = self.set_zn(val);
     }
 
     fn plp(&mut self) {
+        self.dummy_read_pc();
+        self.dummy_read_stack();
         let val = self.pop_byte();
-        self.regs.p = val;
+        self.set_flags(val);
     }
 
     fn php(&mut self) {
+        self.dummy_read_pc();
         let flags = self.regs.p;
-        self.push_byte(flags);
+        self.push_byte(flags | F_BREAK | 0x20);
     }
     
     fn asl<M: AddressingMode>(&mut self, mode: M) {
-        let mut val = mode.load(self);
-        self.set_flag(F_CARRY, 0x80 & val != 0);
-        val = val << 1;
-        val = self.set_zn(val);
-        mode.store(self, val);
+        self.read_modify_write(&mode, |cpu, val| cpu.shift_left(val));
     }
 
     fn adc<M: AddressingMode>(&mut self, mode: M) {
         let val = mode.load(self);
+        self.add_with_carry(val);
+    }
+
+    fn add_with_carry(&mut self, val: u8) {
         let mut result = val as u32;
         result += self.regs.a as u32;
         if self.get_flag(F_CARRY) {
@@ -691,21 +1638,168 @@ impl<'a> CPU<'a> {
         self.set_flag(F_CARRY, (result & 0x100) != 0);
         let result = result as u8;
         let a = self.regs.a;
-        self.set_flag(F_OVERFLOW, (a^val) & 0x80 == 0 && (a^result) & 0x80 == 0);
+        self.set_flag(F_OVERFLOW, (a^val) & 0x80 == 0 && (a^result) & 0x80 != 0);
+        self.regs.a = self.set_zn(result);
+    }
+
+    // Unofficial opcodes. Most of them are a read-modify-write instruction
+    // glued to an ALU instruction using the same operand
+
+    fn nop_read<M: AddressingMode>(&mut self, mode: M) {
+        mode.load(self);
+    }
+
+    fn slo<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |cpu, val| cpu.shift_left(val));
+        let result = self.regs.a | val;
+        self.regs.a = self.set_zn(result);
+    }
+
+    fn rla<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |cpu, val| cpu.rotate_left(val));
+        let result = self.regs.a & val;
+        self.regs.a = self.set_zn(result);
+    }
+
+    fn sre<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |cpu, val| cpu.shift_right(val));
+        let result = self.regs.a ^ val;
+        self.regs.a = self.set_zn(result);
+    }
+
+    fn rra<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |cpu, val| cpu.rotate_right(val));
+        self.add_with_carry(val);
+    }
+
+    fn dcp<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |_, val| val.wrapping_sub(1));
+        let a = self.regs.a;
+        self.compare_value(a, val);
+    }
+
+    fn isc<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.read_modify_write(&mode, |_, val| val.wrapping_add(1));
+        self.add_with_carry(!val);
+    }
+
+    fn lax<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self);
+        self.regs.a = self.set_zn(val);
+        self.regs.x = val;
+    }
+
+    fn sax<M: AddressingMode>(&mut self, mode: M) {
+        let val = self.regs.a & self.regs.x;
+        mode.store(self, val);
+    }
+
+    fn anc<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self) & self.regs.a;
+        self.regs.a = self.set_zn(val);
+        self.set_flag(F_CARRY, val & 0x80 != 0);
+    }
+
+    fn alr<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self) & self.regs.a;
+        self.regs.a = self.shift_right(val);
+    }
+
+    // ROR of A & operand, but C and V come from bits 6 and 5 of the result
+    fn arr<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self) & self.regs.a;
+        let carry = if self.get_flag(F_CARRY) { 0x80 } else { 0 };
+        let result = self.set_zn((val >> 1) | carry);
+        self.set_flag(F_CARRY, result & 0x40 != 0);
+        self.set_flag(F_OVERFLOW, ((result >> 6) ^ (result >> 5)) & 0x01 != 0);
+        self.regs.a = result;
+    }
+
+    fn axs<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self);
+        let ax = self.regs.a & self.regs.x;
+        self.set_flag(F_CARRY, ax >= val);
+        self.regs.x = self.set_zn(ax.wrapping_sub(val));
+    }
+
+    fn las<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self) & self.regs.s;
+        self.regs.a = self.set_zn(val);
+        self.regs.x = val;
+        self.regs.s = val;
+    }
+
+    // XAA and LXA depend on analog effects on the data bus and differ between
+    // chips. We use the magic constants most often measured on the 2A03:
+    // $EE for XAA and $FF for LXA
+    fn xaa<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self);
+        let result = (self.regs.a | 0xEE) & self.regs.x & val;
         self.regs.a = self.set_zn(result);
     }
+
+    fn lxa<M: AddressingMode>(&mut self, mode: M) {
+        let val = mode.load(self);
+        let result = (self.regs.a | 0xFF) & val;
+        self.regs.a = self.set_zn(result);
+        self.regs.x = result;
+    }
+
+    fn sha(&mut self, mode: MemoryAddressingMode) {
+        let val = self.regs.a & self.regs.x;
+        self.unstable_store(mode, val);
+    }
+
+    fn shx(&mut self, mode: MemoryAddressingMode) {
+        let val = self.regs.x;
+        self.unstable_store(mode, val);
+    }
+
+    fn shy(&mut self, mode: MemoryAddressingMode) {
+        let val = self.regs.y;
+        self.unstable_store(mode, val);
+    }
+
+    fn tas(&mut self, mode: MemoryAddressingMode) {
+        self.regs.s = self.regs.a & self.regs.x;
+        let val = self.regs.s;
+        self.unstable_store(mode, val);
+    }
+
+    // SHA, SHX, SHY and TAS store the register ANDed with the high byte of
+    // the base address plus one. When indexing crosses a page the stored
+    // value also replaces the high byte of the target address.
+    fn unstable_store(&mut self, mode: MemoryAddressingMode, val: u8) {
+        let uncarried = mode.uncarried.unwrap_or(mode.val);
+        self.load_byte(uncarried);
+        let result = val & ((uncarried >> 8) as u8).wrapping_add(1);
+        let addr = if uncarried != mode.val {
+            ((result as u16) << 8) | (mode.val & 0x00FF)
+        } else {
+            mode.val
+        };
+        self.store_byte(addr, result);
+    }
 }
 
-impl<'a> Memory for CPU<'a> {
+impl Memory for CPU {
     fn load_byte(&mut self, addr: u16) -> u8 {
-        self.mem_map.load_byte(addr)
+        self.begin_cycle();
+        let val = self.mem_map.load_byte(addr);
+        self.end_cycle();
+        val
     }
 
     fn store_byte(&mut self, addr: u16, val: u8) {
+        self.begin_cycle();
         if addr == 0x4014 {
-            self.dma(val);
-        } else {
-            self.mem_map.store_byte(addr, val);
+            self.dma_page = Some(val);
         }
+        self.mem_map.store_byte(addr, val);
+        self.end_cycle();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.mem_map.peek(addr)
     }
 }
@@ -0,0 +1,168 @@
+use std::fmt;
+use core::cpu::{Mode, OPCODES, NMI_VECTOR, RESET_VECTOR, IRQ_VECTOR};
+use core::memory::Memory;
+use core::rom::Rom;
+
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub official: bool,
+    pub mode: Mode,
+    pub operand: String,
+    // Address the operand refers to, when it can be known without
+    // looking at the registers
+    pub target: Option<u16>
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let mnemonic = if self.official {
+            self.mnemonic.to_owned()
+        } else {
+            format!("*{}", self.mnemonic)
+        };
+        let line = format!("{:04X}  {:8} {:>4} {}", self.addr, bytes.join(" "), mnemonic, self.operand);
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+pub fn disassemble<M: Memory + ?Sized>(mem: &M, addr: u16, count: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut addr = addr;
+
+    for _ in 0..count {
+        let instruction = disassemble_one(mem, addr);
+        addr = addr.wrapping_add(instruction.bytes.len() as u16);
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+fn disassemble_one<M: Memory + ?Sized>(mem: &M, addr: u16) -> Instruction {
+    let opcode = &OPCODES[mem.peek(addr) as usize];
+    let bytes: Vec<u8> = (0..opcode.bytes as u16)
+        .map(|i| mem.peek(addr.wrapping_add(i)))
+        .collect();
+
+    let byte = if bytes.len() > 1 { bytes[1] } else { 0 };
+    let word = if bytes.len() > 2 { (bytes[2] as u16) << 8 | byte as u16 } else { byte as u16 };
+
+    let (operand, target) = match opcode.mode {
+        Mode::Implied => (String::new(), None),
+        Mode::Accumulator => ("A".to_owned(), None),
+        Mode::Immediate => (format!("#${:02X}", byte), None),
+        Mode::ZeroPage => (format!("${:02X}", byte), Some(byte as u16)),
+        Mode::ZeroPageX => (format!("${:02X},X", byte), None),
+        Mode::ZeroPageY => (format!("${:02X},Y", byte), None),
+        Mode::Absolute => (format!("${:04X}", word), Some(word)),
+        Mode::AbsoluteX => (format!("${:04X},X", word), None),
+        Mode::AbsoluteY => (format!("${:04X},Y", word), None),
+        Mode::IndexedIndirect => (format!("(${:02X},X)", byte), None),
+        Mode::IndirectIndexed => (format!("(${:02X}),Y", byte), None),
+        Mode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            (format!("${:04X}", target), Some(target))
+        },
+        // Same page wrapping bug as the CPU
+        Mode::Indirect => {
+            let low = mem.peek(word);
+            let hi = mem.peek((word & 0xFF00) | (word.wrapping_add(1) & 0x00FF));
+            (format!("(${:04X})", word), Some((hi as u16) << 8 | low as u16))
+        }
+    };
+
+    Instruction {
+        addr,
+        bytes,
+        mnemonic: opcode.mnemonic,
+        official: opcode.official,
+        mode: opcode.mode,
+        operand,
+        target
+    }
+}
+
+// A single PRG bank as it appears on the CPU bus
+struct PrgBank<'a> {
+    data: &'a [u8],
+    base: u16
+}
+
+impl<'a> Memory for PrgBank<'a> {
+    fn load_byte(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        let offset = addr.wrapping_sub(self.base) as usize;
+        if offset < self.data.len() { self.data[offset] } else { 0 }
+    }
+
+    fn store_byte(&mut self, _addr: u16, _val: u8) {}
+}
+
+// Lists every 16KB PRG bank. The last bank is shown at $C000 where it sits
+// on boot, the rest at $8000. This is a linear sweep, so data mixed in with
+// code gets disassembled as well.
+pub fn disassemble_prg(rom: &Rom) -> String {
+    let vectors = [
+        ("nmi", NMI_VECTOR, rom.vector(NMI_VECTOR)),
+        ("reset", RESET_VECTOR, rom.vector(RESET_VECTOR)),
+        ("irq", IRQ_VECTOR, rom.vector(IRQ_VECTOR))
+    ];
+
+    let mut listing = String::new();
+    let banks: Vec<&[u8]> = rom.prg_rom.chunks(0x4000).collect();
+
+    for (number, data) in banks.iter().enumerate() {
+        let last = number == banks.len() - 1;
+        let base = if last { 0xC000 } else { 0x8000 };
+        let end = if last { NMI_VECTOR as u32 } else { base as u32 + data.len() as u32 };
+        let bank = PrgBank { data, base };
+
+        listing += &format!("; bank {} at ${:04X}\n", number, base);
+
+        let mut addr = base as u32;
+        while addr < end {
+            if last {
+                for &(label, _, target) in vectors.iter() {
+                    if target as u32 == addr {
+                        listing += &format!("{}:\n", label);
+                    }
+                }
+            }
+
+            let instruction = disassemble_one(&bank, addr as u16);
+            let next = addr + instruction.bytes.len() as u32;
+
+            // Resynchronize on vector targets hidden inside the operand of
+            // whatever came before them
+            let hidden = vectors.iter()
+                .map(|&(_, _, target)| target as u32)
+                .filter(|&target| last && target > addr && target < next)
+                .min();
+
+            if let Some(target) = hidden {
+                let bytes: Vec<String> = instruction.bytes[..(target - addr) as usize].iter()
+                    .map(|b| format!("${:02X}", b))
+                    .collect();
+                listing += &format!("{:04X}  .byte {}\n", addr, bytes.join(", "));
+                addr = target;
+            } else {
+                listing += &format!("{}\n", instruction);
+                addr = next;
+            }
+        }
+
+        if last {
+            for &(label, vector, target) in vectors.iter() {
+                listing += &format!("{:04X}  .word ${:04X} ; {}\n", vector, target, label);
+            }
+        }
+    }
+
+    listing
+}
@@ -0,0 +1,36 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use core::cpu::Registers;
+
+// Anything that stops emulation. Errors raised while executing an
+// instruction carry the CPU registers at the time they happened.
+#[derive(Debug)]
+pub enum EmuError {
+    Io(io::Error),
+    InvalidRom(String),
+    InvalidPalette(String),
+    UnsupportedMapper(u8),
+    IllegalWrite { val: u8, regs: Registers }
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmuError::Io(ref e) => write!(f, "I/O error: {}", e),
+            EmuError::InvalidRom(ref reason) => write!(f, "Invalid rom: {}", reason),
+            EmuError::InvalidPalette(ref reason) => write!(f, "Invalid palette: {}", reason),
+            EmuError::UnsupportedMapper(number) => write!(f, "Unimplemented mapper: {}", number),
+            EmuError::IllegalWrite { val, ref regs } =>
+                write!(f, "Attempted write of {:X} with immediate addressing mode\nRegisters on crash: {}", val, regs)
+        }
+    }
+}
+
+impl Error for EmuError {}
+
+impl From<io::Error> for EmuError {
+    fn from(e: io::Error) -> EmuError {
+        EmuError::Io(e)
+    }
+}
@@ -0,0 +1,95 @@
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    // Opcode reads. These are reported as fetches instead of reads.
+    Fetch
+}
+
+// Called with the address and the value read or about to be written.
+// Returning Some replaces that value, which is how cheats patch memory.
+pub type HookFn = Box<dyn FnMut(u16, u8) -> Option<u8> + Send>;
+
+struct Hook {
+    id: usize,
+    access: Access,
+    range: RangeInclusive<u16>,
+    callback: HookFn
+}
+
+// Callbacks on CPU bus accesses for watchpoints, cheats, loggers and scripts
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    next_id: usize
+}
+
+impl Hooks {
+    pub fn new() -> Hooks {
+        Hooks {
+            hooks: Vec::new(),
+            next_id: 0
+        }
+    }
+
+    // Returns an id to remove the hook with later
+    pub fn add<F>(&mut self, access: Access, range: RangeInclusive<u16>, callback: F) -> usize
+        where F: FnMut(u16, u8) -> Option<u8> + Send + 'static {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.hooks.push(Hook {
+            id,
+            access,
+            range,
+            callback: Box::new(callback)
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|hook| hook.id != id);
+        self.hooks.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    // Runs every matching hook in the order they were added, each one seeing
+    // the value left by the one before. Kept small so the empty case inlines
+    // into the bus.
+    #[inline]
+    pub fn run(&mut self, access: Access, addr: u16, val: u8) -> u8 {
+        if self.hooks.is_empty() {
+            val
+        } else {
+            self.run_hooks(access, addr, val)
+        }
+    }
+
+    fn run_hooks(&mut self, access: Access, addr: u16, val: u8) -> u8 {
+        let mut val = val;
+        for hook in self.hooks.iter_mut() {
+            if hook.access == access && hook.range.contains(&addr) {
+                if let Some(new) = (hook.callback)(addr, val) {
+                    val = new;
+                }
+            }
+        }
+        val
+    }
+}
+
+// Hooks belong to whoever registered them, so like the trace sink they
+// aren't carried over into clones
+impl Clone for Hooks {
+    fn clone(&self) -> Hooks {
+        Hooks::new()
+    }
+}
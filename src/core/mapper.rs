@@ -1,33 +1,95 @@
-use core::rom::Rom;
+use core::error::EmuError;
+use core::rom::{Mirroring, Rom};
 
-pub trait Mapper {
+// Mappers are sent across threads along with the rest of the machine and
+// cloned for save states
+pub trait Mapper: MapperClone + Send {
     fn type_of(&self) -> String;
     fn get_rom(&self) -> &Rom;
     fn load_prg_byte(&self, addr: u16) -> u8;
     fn load_chr_byte(&self, addr: u16) -> u8;
     fn store_prg_byte(&mut self, addr: u16, val: u8);
     fn store_chr_byte(&mut self, addr: u16, val: u8);
+
+    // load_prg_byte for debuggers. Mappers whose reads have side effects
+    // override this.
+    fn peek_prg_byte(&self, addr: u16) -> u8 {
+        self.load_prg_byte(addr)
+    }
+
+    // Runs once per CPU cycle, for mappers with cycle counting IRQs
+    fn clock(&mut self) {}
+
+    // Whether anything on the cartridge answers reads at this address.
+    // Reads nothing answers see open bus.
+    fn prg_mapped(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+
+    // Asked on every nametable access, so mappers that switch mirroring
+    // just return their current setting
+    fn mirroring(&self) -> Mirroring {
+        self.get_rom().header.mirroring()
+    }
 }
 
-pub fn select_mapper(rom: Rom) -> Box<Mapper> {
+pub trait MapperClone {
+    fn box_clone(&self) -> Box<dyn Mapper>;
+}
+
+impl<T: 'static + Mapper + Clone> MapperClone for T {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Mapper> {
+    fn clone(&self) -> Box<dyn Mapper> {
+        self.box_clone()
+    }
+}
+
+pub fn select_mapper(rom: Rom) -> Result<Box<Mapper>, EmuError> {
     let mapper_number = rom.header.mapper_number();
     info!("Mapper number: {:X}", mapper_number);
-    
+
+    // Every address a mapper decodes lands somewhere in these, so accesses
+    // can't fail once they're whole banks
+    if rom.prg_rom.is_empty() || !rom.prg_rom.len().is_multiple_of(0x4000) {
+        return Err(EmuError::InvalidRom(format!("PRG ROM of {} bytes isn't made of 16 KB banks.", rom.prg_rom.len())));
+    }
+    if !rom.chr_rom.len().is_multiple_of(0x2000) {
+        return Err(EmuError::InvalidRom(format!("CHR ROM of {} bytes isn't made of 8 KB banks.", rom.chr_rom.len())));
+    }
+
     match mapper_number {
-        0 => Box::new(NROM::new(rom)) as Box<Mapper>,
-        1 => Box::new(SxROM::new(rom)) as Box<Mapper>,
-        _ => panic!("Unimplemented mapper: {}", mapper_number)
+        0 => Ok(Box::new(NROM::new(rom)) as Box<Mapper>),
+        1 => Ok(Box::new(SxROM::new(rom)) as Box<Mapper>),
+        3 if rom.chr_rom.is_empty() => Err(EmuError::InvalidRom("CNROM needs CHR ROM.".to_string())),
+        3 => Ok(Box::new(CNROM::new(rom)) as Box<dyn Mapper>),
+        _ => Err(EmuError::UnsupportedMapper(mapper_number))
     }
 }
 
+#[derive(Clone)]
 pub struct NROM {
-    rom: Rom
+    rom: Rom,
+    prg_ram: [u8; 0x2000],
+    // Carts without CHR ROM have 8 KB of CHR RAM instead
+    chr_ram: Option<Vec<u8>>
 }
 
 impl NROM {
     pub fn new(rom: Rom) -> NROM {
+        let chr_ram = if rom.chr_rom.is_empty() {
+            Some(vec![0; 0x2000])
+        } else {
+            None
+        };
         NROM {
-            rom
+            rom,
+            prg_ram: [0; 0x2000],
+            chr_ram
         }
     }
 }
@@ -41,6 +103,64 @@ impl Mapper for NROM {
         return &self.rom;
     }
     
+    fn load_prg_byte(&self, addr: u16) -> u8 {
+        if addr < 0x6000 {
+            0
+        } else if addr < 0x8000 {
+            self.prg_ram[addr as usize & 0x1FFF]
+        } else if self.rom.prg_rom.len() > 0x4000 {
+            self.rom.prg_rom[addr as usize & 0x7fff]
+        } else {
+            self.rom.prg_rom[addr as usize & 0x3fff]
+        }
+    }
+
+    fn load_chr_byte(&self, addr: u16) -> u8 {
+        match self.chr_ram {
+            Some(ref chr_ram) => chr_ram[addr as usize & 0x1FFF],
+            None => self.rom.chr_rom[addr as usize & 0x1FFF]
+        }
+    }
+
+    fn store_prg_byte(&mut self, addr: u16, val: u8) {
+        if (0x6000..0x8000).contains(&addr) {
+            self.prg_ram[addr as usize & 0x1FFF] = val;
+        }
+    }
+
+    fn store_chr_byte(&mut self, addr: u16, val: u8) {
+        if let Some(ref mut chr_ram) = self.chr_ram {
+            chr_ram[addr as usize & 0x1FFF] = val;
+        }
+    }
+}
+
+// NROM with the 8 KB of CHR switched by writing the bank number anywhere in
+// $8000-$FFFF. There's no PRG RAM.
+#[derive(Clone)]
+pub struct CNROM {
+    rom: Rom,
+    chr_bank: u8
+}
+
+impl CNROM {
+    pub fn new(rom: Rom) -> CNROM {
+        CNROM {
+            rom,
+            chr_bank: 0
+        }
+    }
+}
+
+impl Mapper for CNROM {
+    fn type_of(&self) -> String {
+        "CNROM".to_string()
+    }
+
+    fn get_rom(&self) -> &Rom {
+        &self.rom
+    }
+
     fn load_prg_byte(&self, addr: u16) -> u8 {
         if addr < 0x8000 {
             0
@@ -52,13 +172,26 @@ impl Mapper for NROM {
     }
 
     fn load_chr_byte(&self, addr: u16) -> u8 {
-        self.rom.chr_rom[addr as usize]
+        let index = self.chr_bank as usize * 0x2000 + (addr as usize & 0x1FFF);
+        self.rom.chr_rom[index % self.rom.chr_rom.len()]
     }
 
-    fn store_prg_byte(&mut self, addr: u16, val: u8) {}
-    fn store_chr_byte(&mut self, addr: u16, val: u8) {}
+    // The ROM drives the data bus too while the bank is written, so only
+    // bits that are set in both make it through
+    fn store_prg_byte(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            self.chr_bank = val & self.load_prg_byte(addr);
+        }
+    }
+
+    fn store_chr_byte(&mut self, _addr: u16, _val: u8) {}
+
+    fn prg_mapped(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
 }
 
+#[derive(Clone)]
 pub struct TestMapper {
     pub rom: Rom,
     mem: [u8; 0x2000]
@@ -113,7 +246,11 @@ impl Mapper for TestMapper {
     fn store_chr_byte(&mut self, addr: u16, val: u8) { }
 }
 
+#[derive(Clone)]
 pub struct SxROMRegisters {
+    // Bits written to $8000-$FFFF one at a time. The 1 shifted in on reset
+    // reaches bit 0 once four bits have been written.
+    shift: u8,
     ctrl: u8,
     chr_bank0: u8,
     chr_bank1: u8,
@@ -123,6 +260,7 @@ pub struct SxROMRegisters {
 impl SxROMRegisters {
     pub fn new() -> SxROMRegisters {
         SxROMRegisters {
+            shift: 0x10,
             ctrl: 12,
             chr_bank0: 0,
             chr_bank1: 0,
@@ -131,9 +269,12 @@ impl SxROMRegisters {
     }
 }
 
+#[derive(Clone)]
 pub struct SxROM {
     rom: Rom,
     regs: SxROMRegisters,
+    prg_ram: [u8; 0x2000],
+    // Used instead of CHR ROM on carts that don't have any
     chr_ram: [u8; 0x2000]
 }
 
@@ -142,9 +283,41 @@ impl SxROM {
         SxROM {
             rom,
             regs: SxROMRegisters::new(),
+            prg_ram: [0; 0x2000],
             chr_ram: [0; 0x2000]
         }
     }
+
+    // The 16 KB bank at $8000 or $C000. Control bits 2-3 pick between
+    // switching 32 KB at once, fixing the first bank at $8000 or fixing
+    // the last one at $C000.
+    fn prg_bank(&self, addr: u16) -> usize {
+        let bank = self.regs.prg_bank as usize & 0x0F;
+        let last = self.rom.prg_rom.len() / 0x4000 - 1;
+        match (self.regs.ctrl >> 2) & 0x03 {
+            0 | 1 => (bank & !0x01) | ((addr as usize >> 14) & 0x01),
+            2 if addr < 0xC000 => 0,
+            2 => bank,
+            _ if addr < 0xC000 => bank,
+            _ => last
+        }
+    }
+
+    // The 4 KB bank at $0000 or $1000, switched together as 8 KB unless
+    // control bit 4 is set
+    fn chr_bank(&self, addr: u16) -> usize {
+        if self.regs.ctrl & 0x10 == 0 {
+            (self.regs.chr_bank0 as usize & !0x01) | ((addr as usize >> 12) & 0x01)
+        } else if addr < 0x1000 {
+            self.regs.chr_bank0 as usize
+        } else {
+            self.regs.chr_bank1 as usize
+        }
+    }
+
+    fn chr_index(&self, addr: u16, len: usize) -> usize {
+        (self.chr_bank(addr) * 0x1000 + (addr as usize & 0x0FFF)) % len
+    }
 }
 
 impl Mapper for SxROM {
@@ -156,14 +329,73 @@ impl Mapper for SxROM {
         return &self.rom;
     }
     
+    // Bank numbers past the end of the ROM wrap around, as the unused
+    // high bits aren't connected
     fn load_prg_byte(&self, addr: u16) -> u8 {
-        0x00
+        if addr < 0x6000 {
+            0
+        } else if addr < 0x8000 {
+            self.prg_ram[addr as usize & 0x1FFF]
+        } else {
+            let index = self.prg_bank(addr) * 0x4000 + (addr as usize & 0x3FFF);
+            self.rom.prg_rom[index % self.rom.prg_rom.len()]
+        }
     }
 
     fn load_chr_byte(&self, addr: u16) -> u8 {
-        0x00
+        if self.rom.chr_rom.is_empty() {
+            self.chr_ram[self.chr_index(addr, self.chr_ram.len())]
+        } else {
+            self.rom.chr_rom[self.chr_index(addr, self.rom.chr_rom.len())]
+        }
+    }
+
+    // The registers are loaded serially, bit 0 first. The fifth write puts
+    // the shift register into the one picked by address bits 13-14, and a
+    // write with bit 7 set starts over.
+    fn store_prg_byte(&mut self, addr: u16, val: u8) {
+        if addr < 0x8000 {
+            if addr >= 0x6000 {
+                self.prg_ram[addr as usize & 0x1FFF] = val;
+            }
+            return;
+        }
+
+        if val & 0x80 != 0 {
+            self.regs.shift = 0x10;
+            self.regs.ctrl |= 0x0C;
+            return;
+        }
+
+        let full = self.regs.shift & 0x01 != 0;
+        let shift = (self.regs.shift >> 1) | ((val & 0x01) << 4);
+        if full {
+            match (addr >> 13) & 0x03 {
+                0 => self.regs.ctrl = shift,
+                1 => self.regs.chr_bank0 = shift,
+                2 => self.regs.chr_bank1 = shift,
+                _ => self.regs.prg_bank = shift
+            }
+            self.regs.shift = 0x10;
+        } else {
+            self.regs.shift = shift;
+        }
+    }
+
+    fn store_chr_byte(&mut self, addr: u16, val: u8) {
+        if self.rom.chr_rom.is_empty() {
+            let index = self.chr_index(addr, self.chr_ram.len());
+            self.chr_ram[index] = val;
+        }
     }
 
-    fn store_prg_byte(&mut self, addr: u16, val: u8) {}
-    fn store_chr_byte(&mut self, addr: u16, val: u8) {}
+    // The low two bits of the control register
+    fn mirroring(&self) -> Mirroring {
+        match self.regs.ctrl & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal
+        }
+    }
 }
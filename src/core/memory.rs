@@ -1,17 +1,29 @@
+use core::apu::APU;
+use core::controller::Controller;
+use core::hooks::{Access, Hooks};
 use core::ppu::PPU;
 use core::mapper::Mapper;
+use core::scheduler::{Region, Scheduler};
 
 pub trait Memory {
     fn load_byte(&mut self, addr: u16) -> u8;
     fn store_byte(&mut self, addr: u16, val: u8);
 
+    // Reads without side effects, for debuggers and tools. Nothing is
+    // acknowledged, shifted or advanced.
+    fn peek(&self, addr: u16) -> u8;
+
+    fn peek_word(&self, addr: u16) -> u16 {
+        self.peek(addr) as u16 | (self.peek(addr.wrapping_add(1)) as u16) << 8
+    }
+
     fn load_word(&mut self, addr: u16) -> u16 {
-        self.load_byte(addr) as u16 | (self.load_byte(addr + 1) as u16) << 8
+        self.load_byte(addr) as u16 | (self.load_byte(addr.wrapping_add(1)) as u16) << 8
     }
     
     fn store_word(&mut self, addr: u16, val: u16) {
         self.store_byte(addr, (val & 0xff) as u8);
-        self.store_byte(addr + 1, ((val >> 8) & 0xff) as u8);
+        self.store_byte(addr.wrapping_add(1), ((val >> 8) & 0xff) as u8);
     }
 
     fn load_word_zeropage_wraparound(&mut self, addr: u8) -> u16 {
@@ -22,15 +34,57 @@ pub trait Memory {
     }
 }
 
+// What work RAM holds at power on. Real consoles come up with whatever the
+// chips settle into, which differs between units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RamInit {
+    Zeros,
+    // Every byte $FF
+    Ones,
+    // Four bytes of $00 followed by four bytes of $FF, repeating
+    Pattern,
+    // Pseudo-random bytes, the same for the same seed
+    Random(u64)
+}
+
+#[derive(Clone)]
 pub struct RAM {
     pub mem: [u8; 0x800]
 }
 
 impl RAM {
     pub fn new() -> RAM {
+        RAM::with_init(RamInit::Zeros)
+    }
+
+    pub fn with_init(init: RamInit) -> RAM {
         info!("Creating RAM...");
-        RAM {
+        let mut ram = RAM {
             mem: [0; 0x800]
+        };
+        ram.fill(init);
+        ram
+    }
+
+    pub fn fill(&mut self, init: RamInit) {
+        match init {
+            RamInit::Zeros => self.mem = [0x00; 0x800],
+            RamInit::Ones => self.mem = [0xFF; 0x800],
+            RamInit::Pattern => {
+                for (i, byte) in self.mem.iter_mut().enumerate() {
+                    *byte = if i & 4 == 0 { 0x00 } else { 0xFF };
+                }
+            },
+            RamInit::Random(seed) => {
+                // xorshift64*, with the seed scrambled so that 0 works too
+                let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+                for byte in self.mem.iter_mut() {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *byte = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+                }
+            }
         }
     }
 }
@@ -42,50 +96,189 @@ impl Memory for RAM {
     fn load_byte(&mut self, addr: u16) -> u8 {
         self.mem[addr as usize & 0x07ff]
     }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.mem[addr as usize & 0x07ff]
+    }
     
     fn store_byte(&mut self, addr: u16, val: u8) {
         self.mem[addr as usize & 0x07ff] = val;
     }
 }
 
-pub struct CPUMemoryMap<'a> {
+// Everything the CPU can reach through the bus
+#[derive(Clone)]
+pub struct CPUMemoryMap {
     pub ram: RAM,
-    pub ppu: &'a mut PPU,
-    pub mapper: Box<Mapper>
+    pub ppu: PPU,
+    pub apu: APU,
+    pub controllers: [Controller; 2],
+    pub mapper: Box<Mapper>,
+    pub scheduler: Scheduler,
+    pub hooks: Hooks,
+    pub ram_init: RamInit,
+    // Last value on the data bus. Reads nothing answers return it.
+    pub open_bus: u8
 }
 
-impl<'a> CPUMemoryMap<'a> {
-    pub fn new(ppu: &mut PPU, ram: RAM, mapper: Box<Mapper>) -> CPUMemoryMap {
+impl CPUMemoryMap {
+    pub fn new(ppu: PPU, ram: RAM, mapper: Box<Mapper>) -> CPUMemoryMap {
         CPUMemoryMap {
             ram,
             ppu,
-            mapper
+            apu: APU::new(),
+            controllers: [Controller::new(), Controller::new()],
+            mapper,
+            scheduler: Scheduler::new(Region::NTSC),
+            hooks: Hooks::new(),
+            ram_init: RamInit::Zeros,
+            open_bus: 0
         }
     }
-}
 
-impl<'a> Memory for CPUMemoryMap<'a> {
-    fn load_byte(&mut self, addr: u16) -> u8 {
-        if addr < 0x2000 {
+    pub fn set_region(&mut self, region: Region) {
+        self.scheduler = Scheduler::new(region);
+        self.ppu.set_region(region);
+    }
+
+    // Everything back to how it comes up when the console is switched on.
+    // RAM is filled according to ram_init.
+    pub fn power_on(&mut self) {
+        self.ram.fill(self.ram_init);
+        self.ppu.power_on();
+        self.apu = APU::new();
+        self.controllers = [Controller::new(), Controller::new()];
+        self.scheduler = Scheduler::new(self.scheduler.region());
+        self.open_bus = 0;
+    }
+
+    // The reset button. RAM and most registers keep their values, the PPU
+    // and APU are partly cleared.
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.write(0x4015, 0x00);
+    }
+
+    // One CPU cycle passes
+    pub fn tick(&mut self) {
+        self.scheduler.advance();
+    }
+
+    pub fn irq(&self) -> bool {
+        self.apu.irq()
+    }
+
+    // $4000-$401F. Most of it is write-only APU registers, the controllers
+    // are read through $4016 and $4017 and strobed together through $4016.
+    // Bit 5 of $4015 and the top 3 bits of the controller ports aren't
+    // driven, so they come from open bus.
+    fn load_io_byte(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4015 => (self.apu.read_status() & !0x20) | (self.open_bus & 0x20),
+            0x4016 => (self.open_bus & 0xE0) | self.controllers[0].read(),
+            0x4017 => (self.open_bus & 0xE0) | self.controllers[1].read(),
+            _ => self.open_bus
+        }
+    }
+
+    fn peek_io_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x4015 => (self.apu.peek_status() & !0x20) | (self.open_bus & 0x20),
+            0x4016 => (self.open_bus & 0xE0) | self.controllers[0].peek(),
+            0x4017 => (self.open_bus & 0xE0) | self.controllers[1].peek(),
+            _ => self.open_bus
+        }
+    }
+
+    fn store_io_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4014 => self.ppu.regs.oam_dma = val,
+            0x4016 => {
+                self.controllers[0].write(val);
+                self.controllers[1].write(val);
+            },
+            _ => self.apu.write(addr, val)
+        }
+    }
+
+    // Runs the PPU, APU and mapper up to the current CPU cycle
+    pub fn catch_up(&mut self) {
+        for _ in 0..self.scheduler.take_ppu_dots() {
+            self.ppu.tick(&*self.mapper);
+        }
+        for _ in 0..self.scheduler.take_cpu_cycles() {
+            self.apu.clock();
+            self.mapper.clock();
+        }
+    }
+
+    // An opcode read. Same as load_byte apart from the hooks it runs.
+    pub fn fetch_byte(&mut self, addr: u16) -> u8 {
+        self.read(addr, Access::Fetch)
+    }
+
+    fn read(&mut self, addr: u16, access: Access) -> u8 {
+        // PRG reads don't depend on where the PPU and APU are, so opcode
+        // and operand fetches leave them behind
+        if (0x2000..0x4020).contains(&addr) {
+            self.catch_up();
+        }
+
+        let val = if addr < 0x2000 {
             self.ram.load_byte(addr)
         } else if addr < 0x4000 {
-            self.ppu.load_byte(addr)
-        } else if addr < 0x6000 {
-            0
-        } else {
+            self.ppu.read_register(addr, &*self.mapper)
+        } else if addr < 0x4020 {
+            self.load_io_byte(addr)
+        } else if self.mapper.prg_mapped(addr) {
             self.mapper.load_prg_byte(addr)
-        }
+        } else {
+            self.open_bus
+        };
+        let val = self.hooks.run(access, addr, val);
+        self.open_bus = val;
+        val
+    }
+}
+
+impl Memory for CPUMemoryMap {
+    fn load_byte(&mut self, addr: u16) -> u8 {
+        self.read(addr, Access::Read)
     }
 
     fn store_byte(&mut self, addr: u16, val: u8) {
+        // Registers and anything on the cartridge, which is where mapper
+        // registers live
+        if addr >= 0x2000 {
+            self.catch_up();
+        }
+        let val = self.hooks.run(Access::Write, addr, val);
+        self.open_bus = val;
+
         if addr < 0x2000 {
             self.ram.store_byte(addr, val);
         } else if addr < 0x4000 {
-            self.ppu.store_byte(addr, val);
-        } else if addr < 0x6000 {
-
+            self.ppu.write_register(addr, val, &mut *self.mapper);
+        } else if addr < 0x4020 {
+            self.store_io_byte(addr, val);
         } else {
             self.mapper.store_prg_byte(addr, val);
         }
     }
+
+    // Doesn't catch the PPU and APU up or run hooks, so this shows them as
+    // of the last bus access
+    fn peek(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            self.ram.peek(addr)
+        } else if addr < 0x4000 {
+            self.ppu.peek_register(addr, &*self.mapper)
+        } else if addr < 0x4020 {
+            self.peek_io_byte(addr)
+        } else if self.mapper.prg_mapped(addr) {
+            self.mapper.peek_prg_byte(addr)
+        } else {
+            self.open_bus
+        }
+    }
 }
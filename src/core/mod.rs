@@ -1,7 +1,15 @@
+pub mod apu;
+pub mod controller;
 pub mod cpu;
+pub mod disassembler;
+pub mod error;
+pub mod hooks;
 pub mod mapper;
 pub mod memory;
 pub mod nes;
+pub mod palette;
 pub mod rom;
 pub mod ppu;
+pub mod scheduler;
 pub mod tools;
+pub mod trace;
@@ -1,34 +1,128 @@
+use std::collections::HashSet;
 use core::cpu;
+use core::error::EmuError;
+use core::mapper;
 use core::memory;
 use core::ppu;
+use core::rom::Rom;
+use core::scheduler::Region;
 
-pub struct NES<'a> {
-    pub cpu: cpu::CPU<'a>
+// What stopped one of the stepping methods
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Completed,
+    FrameCompleted,
+    Breakpoint(u16),
+    Jammed(u16)
 }
 
-pub struct NESBuilder<'a> {
-    cpu: Option<cpu::CPU<'a>>
+// The whole machine. The CPU owns the bus, which owns everything else,
+// so a NES can be moved between threads and cloned for save states.
+#[derive(Clone)]
+pub struct NES {
+    pub cpu: cpu::CPU,
+    breakpoints: HashSet<u16>
 }
 
-impl<'a> NESBuilder<'a> {
-    pub fn new() -> NESBuilder<'a> {
+impl NES {
+    // Builds a machine around the rom's mapper and powers it on
+    pub fn new(rom: Rom) -> Result<NES, EmuError> {
+        NES::with_ram_init(rom, memory::RamInit::Zeros)
+    }
+
+    pub fn with_ram_init(rom: Rom, ram_init: memory::RamInit) -> Result<NES, EmuError> {
+        let region = if rom.header.flags_9 & 0x01 != 0 { Region::PAL } else { Region::NTSC };
+        let mapper = mapper::select_mapper(rom)?;
+        let mut cpu = cpu::CPU::new(ppu::PPU::new(), memory::RAM::new(), mapper);
+        cpu.mem_map.set_region(region);
+        cpu.mem_map.ram_init = ram_init;
+        let mut nes = NESBuilder::new()
+            .cpu(cpu)
+            .finalize();
+        nes.power_on();
+        Ok(nes)
+    }
+
+    pub fn power_on(&mut self) {
+        self.cpu.power_on();
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    // Execution stops when PC lands on a breakpoint, before the instruction
+    // there runs. Stepping again continues past it.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn step_instruction(&mut self) -> Result<Event, EmuError> {
+        self.cpu.step()?;
+
+        let pc = self.cpu.regs.pc;
+        if self.cpu.is_jammed() {
+            Ok(Event::Jammed(pc))
+        } else if self.breakpoints.contains(&pc) {
+            Ok(Event::Breakpoint(pc))
+        } else {
+            Ok(Event::Completed)
+        }
+    }
+
+    // Runs whole instructions until at least the given number of CPU cycles
+    // have passed
+    pub fn step_cycles(&mut self, cycles: u64) -> Result<Event, EmuError> {
+        let target = self.cpu.cycles + cycles;
+        while self.cpu.cycles < target {
+            match self.step_instruction()? {
+                Event::Completed => (),
+                event => return Ok(event)
+            }
+        }
+        Ok(Event::Completed)
+    }
+
+    // Runs until the start of the next vblank
+    pub fn run_frame(&mut self) -> Result<Event, EmuError> {
+        let frame = self.cpu.mem_map.ppu.frame;
+        while self.cpu.mem_map.ppu.frame == frame {
+            match self.step_instruction()? {
+                Event::Completed => (),
+                event => return Ok(event)
+            }
+        }
+        Ok(Event::FrameCompleted)
+    }
+}
+
+pub struct NESBuilder {
+    cpu: Option<cpu::CPU>
+}
+
+impl NESBuilder {
+    pub fn new() -> NESBuilder {
         let cpu = None;
-        
+
         NESBuilder {
             cpu: cpu
         }
     }
 
-    pub fn cpu(mut self, cpu: cpu::CPU<'a>) -> NESBuilder<'a> {
+    pub fn cpu(mut self, cpu: cpu::CPU) -> NESBuilder {
         self.cpu = Some(cpu);
         self
     }
 
-    pub fn finalize(self) -> NES<'a> {
+    pub fn finalize(self) -> NES {
         info!("Creating a NES...");
         NES {
-            cpu: self.cpu.unwrap()
+            cpu: self.cpu.unwrap(),
+            breakpoints: HashSet::new()
         }
     }
 }
-
@@ -0,0 +1,132 @@
+use std::fs;
+use core::error::EmuError;
+
+// Maps the 6-bit color indices the PPU outputs to RGB. There are 8 copies of
+// the 64 colors, one for each combination of the emphasis bits in PPUMASK,
+// laid out like the 1536 byte .pal files.
+#[derive(Clone)]
+pub struct Palette {
+    colors: [[u8; 3]; 512]
+}
+
+// The 2C02 as it appears on an NTSC television
+const NTSC: [u32; 64] = [
+    0x666666, 0x002A88, 0x1412A7, 0x3B00A4, 0x5C007E, 0x6E0040, 0x6C0600, 0x561D00,
+    0x333500, 0x0B4800, 0x005200, 0x004F08, 0x00404D, 0x000000, 0x000000, 0x000000,
+    0xADADAD, 0x155FD9, 0x4240FF, 0x7527FE, 0xA01ACC, 0xB71E7B, 0xB53120, 0x994E00,
+    0x6B6D00, 0x388700, 0x0C9300, 0x008F32, 0x007C8D, 0x000000, 0x000000, 0x000000,
+    0xFFFEFF, 0x64B0FF, 0x9290FF, 0xC676FF, 0xF36AFF, 0xFE6ECC, 0xFE8170, 0xEA9E22,
+    0xBCBE00, 0x88D800, 0x5CE430, 0x45E082, 0x48CDDE, 0x4F4F4F, 0x000000, 0x000000,
+    0xFFFEFF, 0xC0DFFF, 0xD3D2FF, 0xE8C8FF, 0xFBC2FF, 0xFEC4EA, 0xFECCC5, 0xF7D8A5,
+    0xE4E594, 0xCFEF96, 0xBDF4AB, 0xB3F3CC, 0xB5EBF2, 0xB8B8B8, 0x000000, 0x000000
+];
+
+// The 2C03 and 2C05 in PlayChoice-10 and Vs. System boards put out RGB
+// directly, 3 bits per channel. The 2C04s have the same colors in four
+// different orders, which aren't here.
+const RGB_2C03: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420,
+    0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630,
+    0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750,
+    0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772,
+    0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000
+];
+
+// How the emphasis bits act on the base colors
+#[derive(Clone, Copy, PartialEq)]
+enum Emphasis {
+    // Each bit darkens the other two channels. PPUMASK bits 5-7 are
+    // red, green and blue.
+    Composite,
+    // Like Composite but with the red and green bits swapped, as on the 2C07
+    CompositeSwapped,
+    // Each bit drives its own channel at full brightness
+    Rgb
+}
+
+impl Palette {
+    pub fn ntsc() -> Palette {
+        Palette::from_base(&base_colors(&NTSC), Emphasis::Composite)
+    }
+
+    // The 2C02's colors with the emphasis bits wired the way the 2C07 has
+    // them. The 2C07's own colors are off from these, getting them means
+    // loading a .pal taken from one.
+    pub fn pal_emphasis() -> Palette {
+        Palette::from_base(&base_colors(&NTSC), Emphasis::CompositeSwapped)
+    }
+
+    pub fn rgb_2c03() -> Palette {
+        let mut colors = [[0; 3]; 64];
+        for (color, &rgb) in colors.iter_mut().zip(RGB_2C03.iter()) {
+            let channel = |shift: u16| (((rgb >> shift) & 0x07) * 255 / 7) as u8;
+            *color = [channel(6), channel(3), channel(0)];
+        }
+        Palette::from_base(&colors, Emphasis::Rgb)
+    }
+
+    // Loads a .pal file, either 64 colors with emphasis worked out the way
+    // the 2C02 does it, or all 512 emphasized colors
+    pub fn load(path: &String) -> Result<Palette, EmuError> {
+        let bytes = fs::read(path)?;
+        match bytes.len() {
+            192 => {
+                let mut colors = [[0; 3]; 64];
+                for (color, rgb) in colors.iter_mut().zip(bytes.chunks(3)) {
+                    color.copy_from_slice(rgb);
+                }
+                Ok(Palette::from_base(&colors, Emphasis::Composite))
+            },
+            1536 => {
+                let mut colors = [[0; 3]; 512];
+                for (color, rgb) in colors.iter_mut().zip(bytes.chunks(3)) {
+                    color.copy_from_slice(rgb);
+                }
+                Ok(Palette { colors })
+            },
+            len => Err(EmuError::InvalidPalette(format!("Expected 192 or 1536 bytes, got {}.", len)))
+        }
+    }
+
+    fn from_base(base: &[[u8; 3]; 64], emphasis: Emphasis) -> Palette {
+        let mut colors = [[0; 3]; 512];
+        for (i, color) in colors.iter_mut().enumerate() {
+            let mut bits = (i >> 6) as u8;
+            if emphasis == Emphasis::CompositeSwapped {
+                bits = (bits & 0x04) | ((bits & 0x01) << 1) | ((bits & 0x02) >> 1);
+            }
+
+            *color = base[i & 0x3F];
+            for (channel, value) in color.iter_mut().enumerate() {
+                let own = 0x01 << channel;
+                match emphasis {
+                    Emphasis::Rgb if bits & own != 0 => *value = 0xFF,
+                    Emphasis::Rgb => (),
+                    _ if bits & !own != 0 => *value = (*value as u32 * 746 / 1000) as u8,
+                    _ => ()
+                }
+            }
+        }
+        Palette { colors }
+    }
+
+    pub fn rgb(&self, index: u8) -> [u8; 3] {
+        self.emphasized(index, 0)
+    }
+
+    // emphasis is PPUMASK bits 5-7 shifted down
+    pub fn emphasized(&self, index: u8, emphasis: u8) -> [u8; 3] {
+        self.colors[((emphasis as usize & 0x07) << 6) | (index as usize & 0x3F)]
+    }
+}
+
+fn base_colors(table: &[u32; 64]) -> [[u8; 3]; 64] {
+    let mut colors = [[0; 3]; 64];
+    for (color, &rgb) in colors.iter_mut().zip(table.iter()) {
+        *color = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+    }
+    colors
+}
@@ -1,4 +1,7 @@
-use core::memory::Memory;
+use core::mapper::Mapper;
+use core::rom::Mirroring;
+use core::palette::Palette;
+use core::scheduler::Region;
 
 const SCREEN_WIDTH: usize = 256;
 const SCREEN_HEIGHT: usize = 240;
@@ -34,7 +37,8 @@ impl Registers {
 
 #[derive(Clone, Copy)]
 pub struct VRAM {
-    pub nametables: [u8; 0x800],
+    // The upper 2 KB stand in for the extra RAM on four-screen carts
+    pub nametables: [u8; 0x1000],
     pub palettes: [u8; 0x20]
 }
 
@@ -43,11 +47,56 @@ pub struct OAM {
     pub oam: [u8; 0x100]
 }
 
+// A sprite picked for the next scanline, with its pattern row already
+// fetched and flipped
+#[derive(Clone, Copy)]
+struct SpriteUnit {
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x: u8
+}
+
+#[derive(Clone)]
 pub struct PPU {
     pub regs: Registers,
     pub vram: VRAM,
     pub oam: OAM,
-    pub screen: Box<[u8; SCREEN_WIDTH*SCREEN_HEIGHT*3]>
+    // The up to 8 sprites found for the next scanline
+    pub secondary_oam: [u8; 0x20],
+    pub screen: Box<[u8; SCREEN_WIDTH*SCREEN_HEIGHT*3]>,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    // Last value written to or read from any register, which is what the
+    // write-only registers read back
+    pub open_bus: u8,
+    // The current VRAM address, the temporary address it's reloaded from
+    // during rendering, and the fine X scroll
+    pub v: u16,
+    pub t: u16,
+    pub fine_x: u8,
+    // Which write of $2005 or $2006 comes next, shared by both
+    pub w: bool,
+    pub palette: Palette,
+    region: Region,
+    odd_frame: bool,
+    // Set by a $2002 read just before vblank starts, which keeps the flag
+    // from being set that frame
+    suppress_vblank: bool,
+    // Background tile fetched for the next 8 pixels, and the shift
+    // registers holding the two tiles being drawn
+    nametable_byte: u8,
+    attribute_bits: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+    sprites: [SpriteUnit; 8],
+    sprite_count: usize,
+    sprite_zero_in_line: bool
 }
 
 impl PPU {
@@ -56,58 +105,586 @@ impl PPU {
         PPU {
             regs: Registers::new(),
             vram: VRAM{
-                nametables: [0; 0x800],
+                nametables: [0; 0x1000],
                 palettes: [0; 0x20]
             },
             oam: OAM{
                 oam: [0; 0x100]
             },
-            screen: Box::new([0x00; SCREEN_WIDTH*SCREEN_HEIGHT*3])
+            secondary_oam: [0xFF; 0x20],
+            screen: Box::new([0x00; SCREEN_WIDTH*SCREEN_HEIGHT*3]),
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            open_bus: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            palette: Palette::ntsc(),
+            region: Region::NTSC,
+            odd_frame: false,
+            suppress_vblank: false,
+            nametable_byte: 0,
+            attribute_bits: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            pattern_shift_low: 0,
+            pattern_shift_high: 0,
+            attribute_shift_low: 0,
+            attribute_shift_high: 0,
+            sprites: [SpriteUnit { pattern_low: 0, pattern_high: 0, attributes: 0, x: 0 }; 8],
+            sprite_count: 0,
+            sprite_zero_in_line: false
         }
     }
 
-    pub fn get_screen(&mut self) -> [u8; SCREEN_WIDTH*SCREEN_HEIGHT*3] {
-        *self.screen
+    // Registers and timing as they come up, memory is left alone
+    pub fn power_on(&mut self) {
+        self.regs = Registers::new();
+        self.open_bus = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.frame = 0;
+        self.odd_frame = false;
+        self.suppress_vblank = false;
+        self.v = 0;
+        self.t = 0;
+        self.fine_x = 0;
+        self.w = false;
     }
-    
-    pub fn put_pixel(&mut self, x: usize, y: usize, color: Pixel) {
-        self.screen[(y * SCREEN_WIDTH + x) * 3 + 0] = color.r;
-        self.screen[(y * SCREEN_WIDTH + x) * 3 + 1] = color.g;
-        self.screen[(y * SCREEN_WIDTH + x) * 3 + 2] = color.b;
+
+    // The reset line only clears $2000, $2001, the scroll, the write
+    // toggle and the read buffer
+    pub fn reset(&mut self) {
+        self.regs.ppu_ctrl = 0;
+        self.regs.ppu_mask = 0;
+        self.regs.ppu_data = 0;
+        self.t = 0;
+        self.fine_x = 0;
+        self.w = false;
+        self.odd_frame = false;
+    }
+
+    // $2000-$2007 as the CPU sees them. Addresses returning open_bus are
+    // write-only.
+    pub fn read_register(&mut self, addr: u16, mapper: &dyn Mapper) -> u8 {
+        let val = match addr & 7 {
+            // Reading clears the vblank flag. A read on the dot before it
+            // gets set sees it clear and keeps it from being set at all.
+            2 => {
+                if self.scanline == 241 && self.dot == 0 {
+                    self.suppress_vblank = true;
+                }
+                let status = self.peek_register(addr, mapper);
+                self.regs.ppu_status &= !0x80;
+                self.w = false;
+                status
+            },
+            7 => {
+                let addr = self.v & 0x3FFF;
+                // Palette reads skip the buffer, which gets the nametable
+                // byte underneath instead
+                let val = if addr >= 0x3F00 {
+                    self.regs.ppu_data = self.read_vram(mapper, addr - 0x1000);
+                    self.peek_register(0x2007, mapper)
+                } else {
+                    let buffered = self.regs.ppu_data;
+                    self.regs.ppu_data = self.read_vram(mapper, addr);
+                    buffered
+                };
+                self.increment_vram_addr();
+                val
+            },
+            _ => self.peek_register(addr, mapper)
+        };
+        self.open_bus = val;
+        val
     }
-}
 
-impl Memory for PPU {
-    fn load_byte(&mut self, addr: u16) -> u8 {
+    pub fn peek_register(&self, addr: u16, mapper: &dyn Mapper) -> u8 {
         match addr & 7 {
-            0 => self.regs.ppu_ctrl,
-            1 => self.regs.ppu_mask,
-            2 => self.regs.ppu_status,
-            3 => 0x00,
-            4 => self.regs.oam_data,
-            5 => 0x00,
-            6 => 0x00,
+            // Only the top 3 bits of $2002 are driven
+            2 => (self.regs.ppu_status & 0xE0) | (self.open_bus & 0x1F),
+            4 => self.oam.oam[self.regs.oam_addr as usize],
+            7 if self.v & 0x3FFF >= 0x3F00 => {
+                // Palette entries are 6 bits wide
+                (self.read_vram(mapper, self.v) & 0x3F) | (self.open_bus & 0xC0)
+            },
             7 => self.regs.ppu_data,
-            _ => panic!("Invalid memory address read from PPU")
+            _ => self.open_bus
         }
     }
-    // Addresses returning 0x00 above are write-only
 
-    fn store_byte(&mut self, addr: u16, val: u8) {
+    pub fn write_register(&mut self, addr: u16, val: u8, mapper: &mut dyn Mapper) {
+        self.open_bus = val;
         match addr & 7 {
-            0 => self.regs.ppu_ctrl = val,
+            0 => {
+                self.regs.ppu_ctrl = val;
+                self.t = (self.t & !0x0C00) | (val as u16 & 0x03) << 10;
+            },
             1 => self.regs.ppu_mask = val,
             2 => (),
             3 => self.regs.oam_addr = val,
-            4 => self.regs.oam_data = val,
-            5 => self.regs.ppu_scroll = val,
-            6 => self.regs.ppu_addr = val,
-            7 => self.regs.ppu_data = val,
-            _ => panic!("Invalid memory address written to on PPU")
+            4 => {
+                let addr = self.regs.oam_addr;
+                self.regs.oam_data = val;
+                self.oam.oam[addr as usize] = val;
+                self.regs.oam_addr = addr.wrapping_add(1);
+            },
+            // Coarse and fine X first, then coarse and fine Y
+            5 => {
+                self.regs.ppu_scroll = val;
+                if !self.w {
+                    self.t = (self.t & !0x001F) | (val as u16 >> 3);
+                    self.fine_x = val & 0x07;
+                } else {
+                    self.t = (self.t & !0x73E0) | (val as u16 & 0x07) << 12 | (val as u16 & 0xF8) << 2;
+                }
+                self.w = !self.w;
+            },
+            // High byte first, v only changes after the low byte
+            6 => {
+                self.regs.ppu_addr = val;
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | (val as u16 & 0x3F) << 8;
+                } else {
+                    self.t = (self.t & 0xFF00) | val as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            },
+            _ => {
+                let addr = self.v & 0x3FFF;
+                self.write_vram(mapper, addr, val);
+                self.increment_vram_addr();
+            }
+        }
+    }
+
+    // After a $2007 access v moves by 1 or 32 depending on PPUCTRL bit 2.
+    // While rendering the access collides with the fetch pipeline and bumps
+    // coarse X and Y instead.
+    fn increment_vram_addr(&mut self) {
+        let render_line = self.scanline < 240 || self.scanline == self.scanlines_per_frame() - 1;
+        if self.rendering_enabled() && render_line {
+            self.increment_x();
+            self.increment_y();
+        } else {
+            let step = if self.regs.ppu_ctrl & 0x04 != 0 { 32 } else { 1 };
+            self.v = self.v.wrapping_add(step) & 0x7FFF;
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    fn scanlines_per_frame(&self) -> u16 {
+        match self.region {
+            Region::NTSC => 262,
+            Region::PAL => 312
+        }
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.regs.ppu_mask & 0x18 != 0
+    }
+
+    pub fn nmi_enabled(&self) -> bool {
+        self.regs.ppu_ctrl & 0x80 != 0
+    }
+
+    // Whether the NMI output is asserted, which takes both the vblank flag
+    // and PPUCTRL bit 7
+    pub fn nmi(&self) -> bool {
+        self.nmi_enabled() && self.regs.ppu_status & 0x80 != 0
+    }
+
+    pub fn show_background(&self) -> bool {
+        self.regs.ppu_mask & 0x08 != 0
+    }
+
+    pub fn show_sprites(&self) -> bool {
+        self.regs.ppu_mask & 0x10 != 0
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.regs.ppu_ctrl & 0x20 != 0 { 16 } else { 8 }
+    }
+
+    // Advances one dot. Scanlines 0-239 are visible, vblank starts on
+    // scanline 241 and the last scanline of the frame is the pre-render line.
+    // frame counts the vblanks that have started.
+    pub fn tick(&mut self, mapper: &dyn Mapper) {
+        self.dot += 1;
+
+        // NTSC skips the last dot of the pre-render line on odd frames
+        // while rendering
+        let pre_render = self.scanlines_per_frame() - 1;
+        let skip = self.region == Region::NTSC && self.odd_frame && self.rendering_enabled();
+        if self.scanline == pre_render && self.dot == 340 && skip {
+            self.dot = 341;
+        }
+
+        if self.dot > 340 {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > pre_render {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+
+        if self.scanline == 241 && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.regs.ppu_status |= 0x80;
+            }
+            self.suppress_vblank = false;
+            self.frame += 1;
+        }
+
+        self.render(mapper);
+    }
+
+    // Every 8 dots the PPU fetches the nametable, attribute and two pattern
+    // bytes of the next background tile, while the shift registers feed out
+    // the current one pixel by pixel. Dots 321-336 prefetch the first two
+    // tiles of the next line. Sprites for the next line are picked and
+    // fetched once the visible part of a line is done.
+    fn render(&mut self, mapper: &dyn Mapper) {
+        let dot = self.dot;
+        let visible = self.scanline < 240;
+        let pre_render = self.scanline == self.scanlines_per_frame() - 1;
+
+        // Vblank, sprite overflow and sprite 0 hit last until the pre-render
+        // line
+        if pre_render && dot == 1 {
+            self.regs.ppu_status &= !0xE0;
+        }
+
+        if self.rendering_enabled() && (visible || pre_render) {
+            if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+                self.shift_background();
+            }
+
+            if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+                match dot % 8 {
+                    1 => {
+                        self.reload_background();
+                        self.nametable_byte = self.read_vram(mapper, 0x2000 | (self.v & 0x0FFF));
+                    },
+                    3 => {
+                        let v = self.v;
+                        let attribute = self.read_vram(mapper, 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                        // Each attribute byte covers a 4x4 tile area, 2 bits
+                        // per 2x2 quadrant
+                        let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                        self.attribute_bits = (attribute >> shift) & 0x03;
+                    },
+                    5 => {
+                        let addr = self.pattern_addr();
+                        self.pattern_low = self.read_vram(mapper, addr);
+                    },
+                    7 => {
+                        let addr = self.pattern_addr() + 8;
+                        self.pattern_high = self.read_vram(mapper, addr);
+                    },
+                    0 => self.increment_x(),
+                    _ => ()
+                }
+            }
+
+            if dot == 256 {
+                self.increment_y();
+            }
+            if dot == 257 {
+                self.reload_background();
+                self.copy_x();
+            }
+            if pre_render && (280..=304).contains(&dot) {
+                self.copy_y();
+            }
+
+            if dot == 257 {
+                if visible {
+                    self.evaluate_sprites();
+                    self.fetch_sprites(mapper);
+                } else {
+                    self.sprite_count = 0;
+                }
+            }
+            // OAMADDR is used as the address while fetching sprites
+            if (257..=320).contains(&dot) {
+                self.regs.oam_addr = 0;
+            }
+        }
+
+        if visible && (1..=256).contains(&dot) {
+            self.output_pixel();
+        }
+    }
+
+    fn pattern_addr(&self) -> u16 {
+        let table = (self.regs.ppu_ctrl as u16 & 0x10) << 8;
+        let fine_y = (self.v >> 12) & 0x07;
+        table | (self.nametable_byte as u16) << 4 | fine_y
+    }
+
+    fn shift_background(&mut self) {
+        if self.show_background() {
+            self.pattern_shift_low <<= 1;
+            self.pattern_shift_high <<= 1;
+            self.attribute_shift_low <<= 1;
+            self.attribute_shift_high <<= 1;
+        }
+    }
+
+    // Loads the fetched tile into the low half of the shift registers. The
+    // attribute bits are stretched over all 8 pixels.
+    fn reload_background(&mut self) {
+        self.pattern_shift_low = (self.pattern_shift_low & 0xFF00) | self.pattern_low as u16;
+        self.pattern_shift_high = (self.pattern_shift_high & 0xFF00) | self.pattern_high as u16;
+        let low = if self.attribute_bits & 0x01 != 0 { 0xFF } else { 0x00 };
+        let high = if self.attribute_bits & 0x02 != 0 { 0xFF } else { 0x00 };
+        self.attribute_shift_low = (self.attribute_shift_low & 0xFF00) | low;
+        self.attribute_shift_high = (self.attribute_shift_high & 0xFF00) | high;
+    }
+
+    // Coarse X lives in bits 0-4 of v and wraps into the next horizontal
+    // nametable
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // Fine Y lives in bits 12-14 and carries into coarse Y in bits 5-9.
+    // Row 29 is the last one in a nametable, rows 30 and 31 are attributes
+    // and wrap without switching nametables.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+        } else {
+            self.v &= !0x7000;
+            let mut coarse_y = (self.v & 0x03E0) >> 5;
+            if coarse_y == 29 {
+                coarse_y = 0;
+                self.v ^= 0x0800;
+            } else if coarse_y == 31 {
+                coarse_y = 0;
+            } else {
+                coarse_y += 1;
+            }
+            self.v = (self.v & !0x03E0) | (coarse_y << 5);
+        }
+    }
+
+    fn copy_x(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_y(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    // Copies the first 8 sprites covering this scanline into secondary OAM.
+    // Sprite Y is one less than the first line the sprite shows up on, so
+    // they get drawn on the next line.
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let scanline = self.scanline;
+        let in_range = |y: u8| scanline >= y as u16 && scanline < y as u16 + height;
+
+        self.secondary_oam = [0xFF; 0x20];
+        self.sprite_count = 0;
+        self.sprite_zero_in_line = false;
+
+        let mut n = 0;
+        while n < 64 && self.sprite_count < 8 {
+            if in_range(self.oam.oam[n * 4]) {
+                let slot = self.sprite_count * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(&self.oam.oam[n * 4..n * 4 + 4]);
+                self.sprite_zero_in_line |= n == 0;
+                self.sprite_count += 1;
+            }
+            n += 1;
+        }
+
+        // Looking for a ninth sprite, the hardware increments the byte
+        // offset along with the sprite number, so it compares tile numbers,
+        // attributes and X positions against the scanline as well
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.oam.oam[n * 4 + m]) {
+                self.regs.ppu_status |= 0x20;
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+        }
+    }
+
+    fn fetch_sprites(&mut self, mapper: &dyn Mapper) {
+        let height = self.sprite_height();
+
+        for i in 0..self.sprite_count {
+            let sprite = &self.secondary_oam[i * 4..i * 4 + 4];
+            let (y, tile, attributes, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+
+            let mut row = self.scanline - y as u16;
+            if attributes & 0x80 != 0 {
+                row = height - 1 - row;
+            }
+
+            // 8x16 sprites take the pattern table from bit 0 of the tile
+            // number and use two tiles in a row
+            let addr = if height == 16 {
+                let table = (tile as u16 & 0x01) << 12;
+                let tile = (tile as u16 & 0xFE) + (row >> 3);
+                table | tile << 4 | (row & 0x07)
+            } else {
+                let table = (self.regs.ppu_ctrl as u16 & 0x08) << 9;
+                table | (tile as u16) << 4 | row
+            };
+
+            let mut low = self.read_vram(mapper, addr);
+            let mut high = self.read_vram(mapper, addr + 8);
+            if attributes & 0x40 != 0 {
+                low = low.reverse_bits();
+                high = high.reverse_bits();
+            }
+
+            self.sprites[i] = SpriteUnit { pattern_low: low, pattern_high: high, attributes, x };
         }
     }
+
+    // The background pixel as a 2-bit color and palette number
+    fn background_pixel(&self, x: usize) -> (u8, u8) {
+        if !self.show_background() || (x < 8 && self.regs.ppu_mask & 0x02 == 0) {
+            return (0, 0);
+        }
+        let bit = 0x8000 >> self.fine_x;
+        let pixel = ((self.pattern_shift_high & bit != 0) as u8) << 1
+            | (self.pattern_shift_low & bit != 0) as u8;
+        let palette = ((self.attribute_shift_high & bit != 0) as u8) << 1
+            | (self.attribute_shift_low & bit != 0) as u8;
+        (pixel, palette)
+    }
+
+    // The first opaque sprite pixel: its color, palette, whether it goes
+    // behind the background and whether it belongs to sprite 0
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, bool)> {
+        if !self.show_sprites() || (x < 8 && self.regs.ppu_mask & 0x04 == 0) {
+            return None;
+        }
+        for (i, sprite) in self.sprites[..self.sprite_count].iter().enumerate() {
+            let offset = x as i32 - sprite.x as i32;
+            if !(0..8).contains(&offset) {
+                continue;
+            }
+            let shift = 7 - offset;
+            let pixel = ((sprite.pattern_high >> shift) & 0x01) << 1 | ((sprite.pattern_low >> shift) & 0x01);
+            if pixel != 0 {
+                let behind = sprite.attributes & 0x20 != 0;
+                return Some((pixel, (sprite.attributes & 0x03) + 4, behind, i == 0 && self.sprite_zero_in_line));
+            }
+        }
+        None
+    }
+
+    fn output_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let y = self.scanline as usize;
+
+        // Transparent pixels show the backdrop color at $3F00. Bits 1 and 2
+        // of PPUMASK hide the background and sprites in the leftmost 8 pixels.
+        let (bg_pixel, bg_palette) = self.background_pixel(x);
+        let entry = match self.sprite_pixel(x) {
+            Some((pixel, palette, behind, sprite_zero)) => {
+                // Sprite 0 hit doesn't happen on the last pixel of the line
+                if sprite_zero && bg_pixel != 0 && x != 255 {
+                    self.regs.ppu_status |= 0x40;
+                }
+                if bg_pixel != 0 && behind {
+                    bg_palette << 2 | bg_pixel
+                } else {
+                    palette << 2 | pixel
+                }
+            },
+            None if bg_pixel != 0 => bg_palette << 2 | bg_pixel,
+            None => 0
+        };
+
+        let mut color = self.vram.palettes[entry as usize];
+        if self.regs.ppu_mask & 0x01 != 0 {
+            color &= 0x30;
+        }
+        let [r, g, b] = self.palette.emphasized(color, self.regs.ppu_mask >> 5);
+        self.put_pixel(x, y, Pixel { r, g, b });
+    }
+
+    // The PPU bus. Pattern tables at $0000-$1FFF come from the cartridge,
+    // $3000-$3EFF mirrors the nametables and $3F20-$3FFF the palettes.
+    fn read_vram(&self, mapper: &dyn Mapper, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        if addr < 0x2000 {
+            mapper.load_chr_byte(addr)
+        } else if addr < 0x3F00 {
+            self.vram.nametables[nametable_addr(mapper.mirroring(), addr)]
+        } else {
+            self.vram.palettes[palette_addr(addr)]
+        }
+    }
+
+    fn write_vram(&mut self, mapper: &mut dyn Mapper, addr: u16, val: u8) {
+        let addr = addr & 0x3FFF;
+        if addr < 0x2000 {
+            mapper.store_chr_byte(addr, val);
+        } else if addr < 0x3F00 {
+            self.vram.nametables[nametable_addr(mapper.mirroring(), addr)] = val;
+        } else {
+            self.vram.palettes[palette_addr(addr)] = val;
+        }
+    }
+
+    pub fn get_screen(&mut self) -> [u8; SCREEN_WIDTH*SCREEN_HEIGHT*3] {
+        *self.screen
+    }
+    
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Pixel) {
+        self.screen[(y * SCREEN_WIDTH + x) * 3 + 0] = color.r;
+        self.screen[(y * SCREEN_WIDTH + x) * 3 + 1] = color.g;
+        self.screen[(y * SCREEN_WIDTH + x) * 3 + 2] = color.b;
+    }
 }
 
+// Index into VRAM.nametables for a $2000-$3EFF address
+fn nametable_addr(mirroring: Mirroring, addr: u16) -> usize {
+    let table = (addr >> 10) & 0x03;
+    let table = match mirroring {
+        Mirroring::Horizontal => table >> 1,
+        Mirroring::Vertical => table & 0x01,
+        Mirroring::SingleScreenLower => 0,
+        Mirroring::SingleScreenUpper => 1,
+        Mirroring::FourScreen => table
+    };
+    ((table << 10) | (addr & 0x03FF)) as usize
+}
+
+// The backdrop entries of the sprite palettes are the ones of the background
+// palettes
+fn palette_addr(addr: u16) -> usize {
+    let addr = addr & 0x1F;
+    if addr & 0x13 == 0x10 {
+        (addr & 0x0F) as usize
+    } else {
+        addr as usize
+    }
+}
 
 pub struct Pixel {
     pub r: u8,
@@ -1,8 +1,19 @@
-use std::io;
 use std::io::prelude::*;
 use std::fs::File;
+use core::error::EmuError;
 
-#[derive(Debug)]
+// How the four nametables at $2000-$2FFF fold into the console's 2 KB of
+// nametable RAM. Four-screen carts bring another 2 KB so each one is unique.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen
+}
+
+#[derive(Clone, Debug)]
 pub struct INesHeader {
     pub magic: [u8; 4],
     pub prg_rom_size: u8,
@@ -19,9 +30,20 @@ impl INesHeader {
     pub fn mapper_number(&self) -> u8 {
         return (self.flags_7 & 0xF0) | (self.flags_6 >> 4);
     }
+
+    // Bit 3 of flags 6 overrides bit 0, which is the soldered mirroring pad
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flags_6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if self.flags_6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Rom {
     pub header: INesHeader,
     pub prg_rom: Vec<u8>,
@@ -29,10 +51,10 @@ pub struct Rom {
 }
 
 impl Rom {
-    pub fn load(path: &String) -> Result<Rom, io::Error> {
-        let mut f = File::open(path).expect("Rom file not found");
+    pub fn load(path: &String) -> Result<Rom, EmuError> {
+        let mut f = File::open(path)?;
         let mut header_buffer = [0;16];
-        f.read_exact(&mut header_buffer).unwrap();
+        f.read_exact(&mut header_buffer)?;
         let header = INesHeader {
             magic: [
                 header_buffer[0],
@@ -51,12 +73,12 @@ impl Rom {
         };
 
         if header.magic != *b"NES\x1a" {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Loaded file is not a NES rom."));
+            return Err(EmuError::InvalidRom("Loaded file is not a NES rom.".to_string()));
         }
 
         let prg_size = header.prg_rom_size as usize * 16384;
         let mut prg_rom = vec![0; prg_size];
-        f.read_exact(&mut prg_rom).unwrap();
+        f.read_exact(&mut prg_rom)?;
         
         
         let chr_size = header.chr_rom_size as usize * 8192;
@@ -70,5 +92,18 @@ impl Rom {
         })
 
     }
+
+    // Reads one of the interrupt vectors from the end of the last PRG bank,
+    // which is where they are on boot for every mapper we support
+    pub fn vector(&self, addr: u16) -> u16 {
+        let len = self.prg_rom.len();
+        let offset = 0x10000 - addr as usize;
+        if offset > len {
+            return 0;
+        }
+        let low = self.prg_rom[len - offset] as u16;
+        let hi = self.prg_rom.get(len - offset + 1).cloned().unwrap_or(0) as u16;
+        (hi << 8) | low
+    }
 }
 
@@ -0,0 +1,72 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    NTSC,
+    PAL
+}
+
+impl Region {
+    // Master clock ticks per CPU cycle and per PPU dot. NTSC runs 3 dots
+    // per CPU cycle, PAL 3.2.
+    fn dividers(self) -> (u64, u64) {
+        match self {
+            Region::NTSC => (12, 4),
+            Region::PAL => (16, 5)
+        }
+    }
+}
+
+// Keeps time in master clock ticks. The CPU advances the clock on every bus
+// cycle, everything else runs lazily and catches up to it when the CPU
+// touches its registers or finishes an instruction.
+#[derive(Clone)]
+pub struct Scheduler {
+    region: Region,
+    cpu_divider: u64,
+    ppu_divider: u64,
+    master: u64,
+    ppu_master: u64,
+    cpu_cycles: u64,
+    synced_cycles: u64
+}
+
+impl Scheduler {
+    pub fn new(region: Region) -> Scheduler {
+        let (cpu_divider, ppu_divider) = region.dividers();
+        Scheduler {
+            region,
+            cpu_divider,
+            ppu_divider,
+            master: 0,
+            ppu_master: 0,
+            cpu_cycles: 0,
+            synced_cycles: 0
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn cpu_cycles(&self) -> u64 {
+        self.cpu_cycles
+    }
+
+    pub fn advance(&mut self) {
+        self.master += self.cpu_divider;
+        self.cpu_cycles += 1;
+    }
+
+    // PPU dots owed since the last call
+    pub fn take_ppu_dots(&mut self) -> u64 {
+        let dots = (self.master - self.ppu_master) / self.ppu_divider;
+        self.ppu_master += dots * self.ppu_divider;
+        dots
+    }
+
+    // CPU cycles the APU and mapper haven't seen yet
+    pub fn take_cpu_cycles(&mut self) -> u64 {
+        let cycles = self.cpu_cycles - self.synced_cycles;
+        self.synced_cycles = self.cpu_cycles;
+        cycles
+    }
+}
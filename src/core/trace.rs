@@ -0,0 +1,90 @@
+use core::cpu::{CPU, Mode, OPCODES};
+use core::memory::Memory;
+
+// Formats the instruction at PC the way Nintendulator logs it, which is
+// also the format of the nestest reference log:
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+pub fn trace_line(cpu: &CPU) -> String {
+    let pc = cpu.regs.pc;
+    let opcode = &OPCODES[cpu.mem_map.peek(pc) as usize];
+
+    let raw: Vec<u8> = (0..opcode.bytes as u16)
+        .map(|i| cpu.mem_map.peek(pc.wrapping_add(i)))
+        .collect();
+    let bytes: Vec<String> = raw.iter().map(|b| format!("{:02X}", b)).collect();
+
+    let mnemonic = if opcode.official {
+        opcode.mnemonic.to_owned()
+    } else {
+        format!("*{}", opcode.mnemonic)
+    };
+
+    let operand = format_operand(cpu, opcode.mnemonic, opcode.mode, &raw);
+    let (scanline, dot) = (cpu.mem_map.ppu.scanline, cpu.mem_map.ppu.dot);
+
+    format!("{:04X}  {:8} {:>4} {:27} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+            pc, bytes.join(" "), mnemonic, operand,
+            cpu.regs.a, cpu.regs.x, cpu.regs.y, cpu.regs.p, cpu.regs.s,
+            scanline, dot, cpu.cycles)
+}
+
+// Operands are followed by the effective address and the value stored
+// there, as long as the instruction touches memory
+fn format_operand(cpu: &CPU, mnemonic: &str, mode: Mode, raw: &[u8]) -> String {
+    let pc = cpu.regs.pc;
+    let byte = if raw.len() > 1 { raw[1] } else { 0 };
+    let word = if raw.len() > 2 { (raw[2] as u16) << 8 | byte as u16 } else { byte as u16 };
+    let (x, y) = (cpu.regs.x, cpu.regs.y);
+
+    match mode {
+        Mode::Implied => String::new(),
+        Mode::Accumulator => "A".to_owned(),
+        Mode::Immediate => format!("#${:02X}", byte),
+        Mode::Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${:04X}", target)
+        },
+        Mode::ZeroPage => {
+            let val = cpu.mem_map.peek(byte as u16);
+            format!("${:02X} = {:02X}", byte, val)
+        },
+        Mode::ZeroPageX | Mode::ZeroPageY => {
+            let (name, index) = if mode == Mode::ZeroPageX { ("X", x) } else { ("Y", y) };
+            let addr = byte.wrapping_add(index);
+            let val = cpu.mem_map.peek(addr as u16);
+            format!("${:02X},{} @ {:02X} = {:02X}", byte, name, addr, val)
+        },
+        Mode::Absolute if mnemonic == "JMP" || mnemonic == "JSR" => format!("${:04X}", word),
+        Mode::Absolute => {
+            let val = cpu.mem_map.peek(word);
+            format!("${:04X} = {:02X}", word, val)
+        },
+        Mode::AbsoluteX | Mode::AbsoluteY => {
+            let (name, index) = if mode == Mode::AbsoluteX { ("X", x) } else { ("Y", y) };
+            let addr = word.wrapping_add(index as u16);
+            let val = cpu.mem_map.peek(addr);
+            format!("${:04X},{} @ {:04X} = {:02X}", word, name, addr, val)
+        },
+        Mode::Indirect => {
+            let low = cpu.mem_map.peek(word);
+            let hi = cpu.mem_map.peek((word & 0xFF00) | (word.wrapping_add(1) & 0x00FF));
+            format!("(${:04X}) = {:04X}", word, (hi as u16) << 8 | low as u16)
+        },
+        Mode::IndexedIndirect => {
+            let pointer = byte.wrapping_add(x);
+            let addr = peek_word_zeropage(cpu, pointer);
+            let val = cpu.mem_map.peek(addr);
+            format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", byte, pointer, addr, val)
+        },
+        Mode::IndirectIndexed => {
+            let base = peek_word_zeropage(cpu, byte);
+            let addr = base.wrapping_add(y as u16);
+            let val = cpu.mem_map.peek(addr);
+            format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", byte, base, addr, val)
+        }
+    }
+}
+
+fn peek_word_zeropage(cpu: &CPU, addr: u8) -> u16 {
+    cpu.mem_map.peek(addr as u16) as u16 | (cpu.mem_map.peek(addr.wrapping_add(1) as u16) as u16) << 8
+}
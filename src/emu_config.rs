@@ -1,17 +1,23 @@
 use std::collections::HashMap;
 
 use config::{Config,File,FileFormat};
+use core::memory::RamInit;
+use core::palette::Palette;
 
 pub struct EmuConfig {
     pub screen_size: u8,
-    pub font_path: String
+    pub font_path: String,
+    pub ram_init: RamInit,
+    pub palette: Palette
 }
 
 impl EmuConfig {
     pub fn new() -> EmuConfig {
         EmuConfig {
             screen_size: 1,
-            font_path: "./nesfont.bmp".to_string()
+            font_path: "./nesfont.bmp".to_string(),
+            ram_init: RamInit::Zeros,
+            palette: Palette::ntsc()
         }
     }
 
@@ -43,7 +49,57 @@ impl EmuConfig {
         if deserialized.contains_key("font_path") {
             result.font_path = deserialized.get("font_path").unwrap().to_string();
         }
-        
+
+        // ram_init is one of zeros, ones, pattern or random, random takes
+        // an optional ram_seed
+        if deserialized.contains_key("ram_init") {
+            let seed = match deserialized.get("ram_seed").map(|seed| seed.parse::<u64>()) {
+                Some(Ok(val)) => val,
+                Some(Err(_)) => {
+                    error!("Invalid value for ram_seed, use an integer. Defaulting to 0.");
+                    0
+                },
+                None => 0
+            };
+            result.ram_init = match deserialized.get("ram_init").unwrap().as_str() {
+                "zeros" => RamInit::Zeros,
+                "ones" => RamInit::Ones,
+                "pattern" => RamInit::Pattern,
+                "random" => RamInit::Random(seed),
+                other => {
+                    error!("Invalid value for ram_init: {}. Defaulting to zeros.", other);
+                    RamInit::Zeros
+                }
+            };
+        }
+
+        // palette is one of ntsc, pal_emphasis or 2c03. There's no 2C07
+        // color table, pal_emphasis is the NTSC colors with the 2C07's
+        // emphasis bits. 2c03 is the 2C03/2C05 RGB PPU, the 2C04 Vs. System
+        // PPUs have their colors in other orders. For those a 1536 byte .pal
+        // in palette_file does it, and takes precedence.
+        if deserialized.contains_key("palette") {
+            result.palette = match deserialized.get("palette").unwrap().as_str() {
+                "ntsc" => Palette::ntsc(),
+                "pal_emphasis" => Palette::pal_emphasis(),
+                "2c03" => Palette::rgb_2c03(),
+                other => {
+                    error!("Invalid value for palette: {}. Defaulting to ntsc.", other);
+                    Palette::ntsc()
+                }
+            };
+        }
+
+        if let Some(path) = deserialized.get("palette_file") {
+            match Palette::load(path) {
+                Ok(palette) => result.palette = palette,
+                Err(e) => {
+                    error!("Palette could not be loaded from {}", path);
+                    error!("{}", e);
+                }
+            }
+        }
+
         result
     }
 }
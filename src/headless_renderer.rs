@@ -2,25 +2,33 @@ use core::ppu::PPU;
 use renderer::{Renderer, RenderingState};
 
 pub struct HeadlessRenderer {
-    rom_path: String
+    rom_path: String,
+    running: bool
 }
 
 impl HeadlessRenderer {
     pub fn new(rom_path: &String) -> HeadlessRenderer {
         info!("Creating a headless renderer...");
         HeadlessRenderer {
-            rom_path: rom_path.to_owned()
+            rom_path: rom_path.to_owned(),
+            running: true
         }
     }
+
+    // Lets the update callback end the main loop
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
 }
 
 impl Renderer<HeadlessRenderer> for HeadlessRenderer {
     fn start_loop<F>(&mut self, mut update: F, run: &RenderingState) where F: FnMut(&mut HeadlessRenderer) {
         info!("Starting main loop");
         info!("Rom: {}", self.rom_path);
+        self.running = true;
         loop {
             update(self);
-            if run.state == "stop" {
+            if run.state == "stop" || !self.running {
                 break;
             }
         };
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 use clap::{App, Arg, ArgMatches};
 use core;
-use core::mapper;
-use core::ppu::Pixel;
+use core::error::EmuError;
+use core::nes::Event;
 use emu_config::EmuConfig;
 use renderer::{Renderer, RenderingState};
 use sdl_renderer::SDLRenderer;
@@ -28,31 +28,60 @@ pub fn read_cl_args<'a>() -> ArgMatches<'a> {
              .short("h")
              .long("headless")
              .help("Run without graphics"))
+        .arg(Arg::with_name("disassemble")
+             .short("d")
+             .long("disassemble")
+             .help("Print a disassembly of the rom's PRG banks and exit"))
         .get_matches()
 }
 
-pub fn start<R: Renderer<SDLRenderer>>(rom: core::rom::Rom, config: EmuConfig, rom_path: &String, mut renderer: Box<R>) {
-    info!("Initializing the emulator");    
-    let mapper = mapper::select_mapper(rom);
-    let mut ppu = core::ppu::PPU::new();
-    
-    let ram = core::memory::RAM::new();
-    let cpu = core::cpu::CPU::new(&mut ppu, ram, mapper);
-
-    let mut nes = core::nes::NESBuilder::new()
-        .cpu(cpu)
-        .finalize();
-    
-    nes.cpu.reset();
+pub fn start<R: Renderer<SDLRenderer>>(rom: core::rom::Rom, config: EmuConfig, rom_path: &String, mut renderer: Box<R>) -> Result<(), EmuError> {
+    info!("Initializing the emulator");
+    let mut nes = core::nes::NES::with_ram_init(rom, config.ram_init)?;
+    nes.cpu.mem_map.ppu.palette = config.palette;
 
+    // Keep the window open with the last frame after the CPU stops
+    let mut halted = false;
     renderer.start_loop(|r: &mut SDLRenderer| {
-        nes.cpu.step();
-        nes.cpu.mem_map.ppu.put_pixel(10, 10, Pixel{r: 0xFF, g: 0xFF, b: 0xFF});
+        if !halted {
+            match nes.run_frame() {
+                Ok(Event::Jammed(pc)) => {
+                    error!("CPU jammed at ${:04X}", pc);
+                    halted = true;
+                },
+                Ok(_) => (),
+                Err(e) => {
+                    error!("{}", e);
+                    halted = true;
+                }
+            }
+        }
         r.render_screen(&mut nes.cpu.mem_map.ppu);
         
     }, &RenderingState{state: "run"});
+
+    Ok(())
 }
 
-pub fn start_headless<R: Renderer<HeadlessRenderer>>(rom: core::rom::Rom, config: EmuConfig, rom_path: &String, mut renderer: Box<R>) {
+pub fn start_headless<R: Renderer<HeadlessRenderer>>(rom: core::rom::Rom, config: EmuConfig, rom_path: &String, mut renderer: Box<R>) -> Result<(), EmuError> {
+    info!("Initializing the emulator");
+    let mut nes = core::nes::NES::with_ram_init(rom, config.ram_init)?;
+    nes.cpu.mem_map.ppu.palette = config.palette;
+
+    let mut result = Ok(());
+    renderer.start_loop(|r: &mut HeadlessRenderer| {
+        match nes.run_frame() {
+            Ok(Event::Jammed(pc)) => {
+                error!("CPU jammed at ${:04X}", pc);
+                r.stop();
+            },
+            Ok(_) => (),
+            Err(e) => {
+                result = Err(e);
+                r.stop();
+            }
+        }
+    }, &RenderingState{state: "run"});
 
+    result
 }
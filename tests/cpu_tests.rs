@@ -3,10 +3,12 @@ extern crate mr_cool_nes;
 #[cfg(test)]
 mod cpu_tests {
     use mr_cool_nes::core::cpu::*;
+    use mr_cool_nes::core::error::EmuError;
     use mr_cool_nes::core::ppu::PPU;
     use mr_cool_nes::core::memory::{Memory, RAM};
     use mr_cool_nes::core::rom::{INesHeader, Rom};
-    use mr_cool_nes::core::mapper::NROM;
+    use mr_cool_nes::core::mapper::{Mapper, NROM};
+    use std::sync::{Arc, Mutex};
 
     fn setup_rom() -> Rom {
         Rom {
@@ -30,17 +32,16 @@ mod cpu_tests {
         PPU::new()
     }
     
-    fn setup_cpu<'a>(ppu: &'a mut PPU) -> CPU<'a> {
+    fn setup_cpu() -> CPU {
         let rom = setup_rom();        
         let ram = RAM::new();
         let mapper = NROM::new(rom);
-        CPU::new(ppu, ram, Box::new(mapper))
+        CPU::new(setup_ppu(), ram, Box::new(mapper))
     }
 
     #[test]
     fn load_byte_ram() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.mem_map.ram.mem[0xFF] = 0xDD;
         let byte = cpu.load_byte(0xFF);
         assert_eq!(byte, 0xDD);
@@ -48,24 +49,21 @@ mod cpu_tests {
 
     #[test]
     fn store_byte_ram() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.store_byte(0x0, 0xDD);
         assert_eq!(cpu.mem_map.ram.mem[0x0], 0xDD);
     }
 
     #[test]
     fn store_byte_vram() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.store_byte(0x2000, 0xDD);
         assert_eq!(cpu.mem_map.ppu.regs.ppu_ctrl, 0xDD);
     }
 
     #[test]
     fn store_byte_dma() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         let final_addr = ((0x0 as u16) << 8) + 256;
         cpu.store_byte(final_addr, 0xDD);
         cpu.store_byte(0x4014, 0x0);
@@ -74,8 +72,7 @@ mod cpu_tests {
 
     #[test]
     fn push_byte_on_stack() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.push_byte(0xDD);
         assert_eq!(cpu.regs.s, 0xFC);
         assert_eq!(cpu.mem_map.ram.mem[(0x100 + (cpu.regs.s + 1) as u16) as usize], 0xDD);
@@ -83,8 +80,7 @@ mod cpu_tests {
 
     #[test]
     fn push_word_on_stack() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.push_word(0xCCDD);
         assert_eq!(cpu.regs.s, 0xFB);
         assert_eq!(cpu.mem_map.ram.mem[(0x100 + (cpu.regs.s + 1) as u16) as usize], 0xDD);
@@ -93,8 +89,7 @@ mod cpu_tests {
 
     #[test]
     fn pop_byte_from_stack() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.s = 0xFC;
         cpu.mem_map.ram.mem[0x100 + 0xFD] = 0xDE;
         let val = cpu.pop_byte();
@@ -105,8 +100,7 @@ mod cpu_tests {
 
     #[test]
     fn pop_word_from_stack() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.s = 0xFC;
         cpu.mem_map.ram.mem[0x100 + 0xFE] = 0xDE;
         cpu.mem_map.ram.mem[0x100 + 0xFD] = 0xAD;
@@ -118,64 +112,56 @@ mod cpu_tests {
 
     #[test]
     fn reset() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.reset();
         assert_eq!(cpu.regs.pc, 0x0000);
     }
 
     #[test]
     fn set_flag_carry() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_CARRY, true);
         assert_eq!(cpu.regs.p & 0x01, 1);
     }
 
     #[test]
     fn set_flag_interrupt() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_INTERRUPT, true);
         assert_eq!(cpu.regs.p & 0x04, 0x04);
     }
 
     #[test]
     fn set_flag_decimal() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_DECIMAL, true);
         assert_eq!(cpu.regs.p & 0x08, 0x08);
     }
 
     #[test]
     fn set_flag_break() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_BREAK, true);
         assert_eq!(cpu.regs.p & 0x10, 0x10);
     }
 
     #[test]
     fn set_flag_overflow() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_OVERFLOW, true);
         assert_eq!(cpu.regs.p & 0x40, 0x40);
     }
 
     #[test]
     fn set_flag_negative() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_flag(F_NEGATIVE, true);
         assert_eq!(cpu.regs.p & 0x80, 0x80);
     }
 
     #[test]
     fn load_byte_increment_pc() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         let pc = cpu.regs.pc;
         cpu.mem_map.ram.mem[pc as usize] = 0xDD;
         let byte = cpu.load_byte_increment_pc();
@@ -185,8 +171,7 @@ mod cpu_tests {
 
     #[test]
     fn load_word_increment_pc() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         let pc = cpu.regs.pc;
         cpu.mem_map.ram.mem[pc as usize] = 0xAD;
         cpu.mem_map.ram.mem[(pc+1) as usize] = 0xDE;
@@ -197,8 +182,7 @@ mod cpu_tests {
 
     #[test]
     fn set_zn_zero() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_zn(0);
         assert_eq!(cpu.regs.p & 0x02, 0x02);
         assert_eq!(cpu.regs.p & 0x80, 0);
@@ -206,8 +190,7 @@ mod cpu_tests {
 
     #[test]
     fn set_zn_nonzero() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_zn(0x40);
         assert_eq!(cpu.regs.p & 0x02, 0);
         assert_eq!(cpu.regs.p & 0x80, 0);
@@ -215,8 +198,7 @@ mod cpu_tests {
 
     #[test]
     fn set_zn_negative() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.set_zn(0xA0);
         assert_eq!(cpu.regs.p & 0x02, 0);
         assert_eq!(cpu.regs.p & 0x80, 0x80);
@@ -227,13 +209,12 @@ mod cpu_tests {
         let mut rom = setup_rom();
         
         let ram = RAM::new();
-        let mut ppu = setup_ppu();
         
         rom.prg_rom[0xFFFC & 0x3FFF] = 0xAD;
         rom.prg_rom[(0xFFFC + 1 )& 0x3FFF] = 0xDE;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         
         cpu.reset();
         assert_eq!(cpu.regs.pc, 0xDEAD);
@@ -244,13 +225,12 @@ mod cpu_tests {
         let mut rom = setup_rom();
 
         let mut ram = RAM::new();
-        let mut ppu = PPU::new();
         
         rom.prg_rom[0xFFFC & 0x3FFF] = 0x00;
         ram.mem[0x00] = 0x10;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         cpu.regs.a = 0x00;
         
         cpu.reset();
@@ -266,13 +246,12 @@ mod cpu_tests {
         let mut rom = setup_rom();
 
         let mut ram = RAM::new();
-        let mut ppu = PPU::new();
         
         rom.prg_rom[0xFFFC & 0x3FFF] = 0x00;
         ram.mem[0x00] = 0x10;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         cpu.regs.a = 0x20;
         
         cpu.reset();
@@ -288,13 +267,12 @@ mod cpu_tests {
         let mut rom = setup_rom();
 
         let mut ram = RAM::new();
-        let mut ppu = PPU::new();
         
         rom.prg_rom[0xFFFC & 0x3FFF] = 0x00;
         ram.mem[0x00] = 0x10;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         cpu.regs.a = 0x10;
         
         cpu.reset();
@@ -309,12 +287,11 @@ mod cpu_tests {
     fn branch_go() {
         let mut rom = setup_rom();
         let ram = RAM::new();
-        let mut ppu = PPU::new();
         
         rom.prg_rom[0xFF00 & 0x3FFF] = 0x04;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         cpu.reset();
         cpu.regs.pc = 0xFF00;
 
@@ -326,12 +303,11 @@ mod cpu_tests {
     fn branch_dont_go() {
         let mut rom = setup_rom();
         let ram = RAM::new();
-        let mut ppu = setup_ppu();
         
         rom.prg_rom[0xFF00 & 0x3FFF] = 0x04;
 
         let mapper = NROM::new(rom);
-        let mut cpu = CPU::new(&mut ppu, ram, Box::new(mapper));
+        let mut cpu = CPU::new(setup_ppu(), ram, Box::new(mapper));
         cpu.reset();
         cpu.regs.pc = 0xFF00;
 
@@ -341,8 +317,7 @@ mod cpu_tests {
 
     #[test]
     fn sta() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.pc = 0x100;
         cpu.mem_map.ram.mem[0x100] = 0xAA;
         cpu.mem_map.ram.mem[0x101] = 0x01;
@@ -354,8 +329,7 @@ mod cpu_tests {
 
     #[test]
     fn stx() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.pc = 0x100;
         cpu.mem_map.ram.mem[0x100] = 0xAA;
         cpu.mem_map.ram.mem[0x101] = 0x01;
@@ -367,8 +341,7 @@ mod cpu_tests {
 
     #[test]
     fn sty() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.pc = 0x100;
         cpu.mem_map.ram.mem[0x100] = 0xAA;
         cpu.mem_map.ram.mem[0x101] = 0x01;
@@ -380,8 +353,7 @@ mod cpu_tests {
 
     #[test]
     fn inc() {
-        let mut ppu = setup_ppu();
-        let mut cpu = setup_cpu(&mut ppu);
+        let mut cpu = setup_cpu();
         cpu.regs.pc = 0x100;
         cpu.mem_map.ram.mem[0xAA] = 0x09;
         cpu.mem_map.ram.mem[0x100] = 0xAA;
@@ -389,4 +361,694 @@ mod cpu_tests {
         cpu.inc(mode);
         assert_eq!(cpu.mem_map.ram.mem[0xAA], 0x0A);
     }
+
+    #[test]
+    fn step_cycles_immediate() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0xA9;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        assert_eq!(cpu.step().unwrap(), 2);
+        assert_eq!(cpu.cycles, 2);
+    }
+
+    #[test]
+    fn step_cycles_page_cross() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = 0x01;
+        cpu.mem_map.ram.mem[0x200] = 0xBD;
+        cpu.mem_map.ram.mem[0x201] = 0xFF;
+        cpu.mem_map.ram.mem[0x202] = 0x02;
+        assert_eq!(cpu.step().unwrap(), 5);
+    }
+
+    #[test]
+    fn step_cycles_store_no_page_penalty() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = 0x01;
+        cpu.mem_map.ram.mem[0x200] = 0x9D;
+        cpu.mem_map.ram.mem[0x201] = 0xFF;
+        cpu.mem_map.ram.mem[0x202] = 0x02;
+        assert_eq!(cpu.step().unwrap(), 5);
+    }
+
+    #[test]
+    fn step_cycles_branch_not_taken() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.set_flag(F_ZERO, false);
+        cpu.mem_map.ram.mem[0x200] = 0xF0;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        assert_eq!(cpu.step().unwrap(), 2);
+    }
+
+    #[test]
+    fn step_cycles_branch_taken() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.set_flag(F_ZERO, true);
+        cpu.mem_map.ram.mem[0x200] = 0xF0;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        assert_eq!(cpu.step().unwrap(), 3);
+        assert_eq!(cpu.regs.pc, 0x212);
+    }
+
+    #[test]
+    fn step_cycles_branch_page_cross() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.set_flag(F_ZERO, true);
+        cpu.mem_map.ram.mem[0x200] = 0xF0;
+        cpu.mem_map.ram.mem[0x201] = 0xF0;
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.regs.pc, 0x1F2);
+    }
+
+    #[test]
+    fn cycles_accumulate() {
+        let mut cpu = setup_cpu();
+        cpu.reset();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0xEA;
+        cpu.mem_map.ram.mem[0x201] = 0x48;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycles, 7 + 2 + 3);
+    }
+
+    #[test]
+    fn zero_page_x_wraparound() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = 0x02;
+        cpu.regs.a = 0xDE;
+        cpu.mem_map.ram.mem[0x200] = 0xFF;
+        let mode = cpu.zero_page_x_addressing_mode();
+        cpu.sta(mode);
+        assert_eq!(cpu.mem_map.ram.mem[0x01], 0xDE);
+    }
+
+    #[test]
+    fn ldx_zero_page_y() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.y = 0x05;
+        cpu.mem_map.ram.mem[0x200] = 0xB6;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        cpu.mem_map.ram.mem[0x15] = 0xDE;
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.x, 0xDE);
+    }
+
+    #[test]
+    fn jmp_indirect_page_wrap() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0x6C;
+        cpu.mem_map.ram.mem[0x201] = 0xFF;
+        cpu.mem_map.ram.mem[0x202] = 0x03;
+        cpu.mem_map.ram.mem[0x3FF] = 0xAD;
+        cpu.mem_map.ram.mem[0x300] = 0x05;
+        cpu.mem_map.ram.mem[0x400] = 0x06;
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0x05AD);
+    }
+
+    #[test]
+    fn adc_overflow() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.a = 0x50;
+        cpu.mem_map.ram.mem[0x200] = 0x69;
+        cpu.mem_map.ram.mem[0x201] = 0x50;
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.a, 0xA0);
+        assert_eq!(cpu.get_flag(F_OVERFLOW), true);
+        assert_eq!(cpu.get_flag(F_CARRY), false);
+        assert_eq!(cpu.get_flag(F_NEGATIVE), true);
+    }
+
+    #[test]
+    fn sbc_borrow() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.a = 0x10;
+        cpu.set_flag(F_CARRY, true);
+        cpu.mem_map.ram.mem[0x200] = 0xE9;
+        cpu.mem_map.ram.mem[0x201] = 0x20;
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.a, 0xF0);
+        assert_eq!(cpu.get_flag(F_CARRY), false);
+        assert_eq!(cpu.get_flag(F_OVERFLOW), false);
+        assert_eq!(cpu.get_flag(F_NEGATIVE), true);
+    }
+
+    #[test]
+    fn lax_zero_page() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0xA7;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        cpu.mem_map.ram.mem[0x10] = 0x80;
+        assert_eq!(cpu.step().unwrap(), 3);
+        assert_eq!(cpu.regs.a, 0x80);
+        assert_eq!(cpu.regs.x, 0x80);
+        assert_eq!(cpu.get_flag(F_NEGATIVE), true);
+    }
+
+    #[test]
+    fn dcp_absolute() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.a = 0x41;
+        cpu.mem_map.ram.mem[0x200] = 0xCF;
+        cpu.mem_map.ram.mem[0x201] = 0x00;
+        cpu.mem_map.ram.mem[0x202] = 0x03;
+        cpu.mem_map.ram.mem[0x300] = 0x42;
+        assert_eq!(cpu.step().unwrap(), 6);
+        assert_eq!(cpu.mem_map.ram.mem[0x300], 0x41);
+        assert_eq!(cpu.get_flag(F_ZERO), true);
+        assert_eq!(cpu.get_flag(F_CARRY), true);
+    }
+
+    #[test]
+    fn arr_flags() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.a = 0xFF;
+        cpu.set_flag(F_CARRY, true);
+        cpu.mem_map.ram.mem[0x200] = 0x6B;
+        cpu.mem_map.ram.mem[0x201] = 0xC0;
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.a, 0xE0);
+        assert_eq!(cpu.get_flag(F_CARRY), true);
+        assert_eq!(cpu.get_flag(F_OVERFLOW), false);
+    }
+
+    #[test]
+    fn nop_absolute_x_consumes_operand() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = 0x01;
+        cpu.mem_map.ram.mem[0x200] = 0x1C;
+        cpu.mem_map.ram.mem[0x201] = 0xFF;
+        cpu.mem_map.ram.mem[0x202] = 0x02;
+        assert_eq!(cpu.step().unwrap(), 5);
+        assert_eq!(cpu.regs.pc, 0x203);
+    }
+
+    #[test]
+    fn shx_stores_x_and_high_byte() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = 0xFF;
+        cpu.regs.y = 0x01;
+        cpu.mem_map.ram.mem[0x200] = 0x9E;
+        cpu.mem_map.ram.mem[0x201] = 0x10;
+        cpu.mem_map.ram.mem[0x202] = 0x03;
+        cpu.step().unwrap();
+        assert_eq!(cpu.mem_map.ram.mem[0x311], 0x04);
+    }
+
+    fn setup_interrupt_cpu() -> CPU {
+        let mut rom = setup_rom();
+        rom.prg_rom[0xFFFA & 0x3FFF] = 0x00;
+        rom.prg_rom[0xFFFB & 0x3FFF] = 0x90;
+        rom.prg_rom[0xFFFE & 0x3FFF] = 0x00;
+        rom.prg_rom[0xFFFF & 0x3FFF] = 0xA0;
+        let mapper = NROM::new(rom);
+        let mut cpu = CPU::new(setup_ppu(), RAM::new(), Box::new(mapper));
+        cpu.regs.pc = 0x200;
+        cpu
+    }
+
+    #[test]
+    fn nmi_jumps_to_vector() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.set_nmi(true);
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.regs.pc, 0x9000);
+        assert_eq!(cpu.get_flag(F_INTERRUPT), true);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FD], 0x02);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FC], 0x00);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FB] & F_BREAK, 0);
+    }
+
+    #[test]
+    fn nmi_edge_triggered() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.set_nmi(true);
+        cpu.step().unwrap();
+        cpu.set_nmi(true);
+        assert_eq!(cpu.nmi_pending(), false);
+        cpu.set_nmi(false);
+        cpu.set_nmi(true);
+        assert_eq!(cpu.nmi_pending(), true);
+    }
+
+    fn run_until_vblank(cpu: &mut CPU) {
+        cpu.mem_map.ram.mem[0x200..0x203].copy_from_slice(&[0x4C, 0x00, 0x02]);
+        while cpu.mem_map.ppu.regs.ppu_status & 0x80 == 0 {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn vblank_raises_nmi() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ppu.regs.ppu_ctrl = 0x80;
+        run_until_vblank(&mut cpu);
+        assert_eq!(cpu.regs.pc, 0x200);
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.regs.pc, 0x9000);
+    }
+
+    #[test]
+    fn vblank_without_nmi_enabled() {
+        let mut cpu = setup_interrupt_cpu();
+        run_until_vblank(&mut cpu);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0x200);
+        assert_eq!(cpu.nmi_pending(), false);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank() {
+        let mut cpu = setup_interrupt_cpu();
+        run_until_vblank(&mut cpu);
+        // LDA #$80, STA $2000, NOP
+        cpu.mem_map.ram.mem[0x210..0x216].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xEA]);
+        cpu.regs.pc = 0x210;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        // The NMI shows up on the last cycle of the write, too late to be
+        // polled before the next instruction
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0x216);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0x9000);
+    }
+
+    // Runs LDA $2002 with NMIs on, with the PPU moved ahead so the read on
+    // the last cycle lands on the given dot
+    fn read_status_at(scanline: u16, dot: u16) -> CPU {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ppu.regs.ppu_ctrl = 0x80;
+        cpu.mem_map.ram.mem[0x200..0x205].copy_from_slice(&[0xAD, 0x02, 0x20, 0xEA, 0xEA]);
+        for _ in 0..(scanline as u32 * 341 + dot as u32 - 12) {
+            cpu.mem_map.ppu.tick(&*cpu.mem_map.mapper);
+        }
+        cpu.step().unwrap();
+        cpu
+    }
+
+    fn nmi_follows(cpu: &mut CPU) -> bool {
+        (0..2).any(|_| {
+            cpu.step().unwrap();
+            cpu.regs.pc == 0x9000
+        })
+    }
+
+    #[test]
+    fn status_read_before_vblank() {
+        let mut cpu = read_status_at(240, 340);
+        assert_eq!(cpu.regs.a & 0x80, 0);
+        assert_eq!(nmi_follows(&mut cpu), true);
+    }
+
+    #[test]
+    fn status_read_suppresses_vblank() {
+        let mut cpu = read_status_at(241, 0);
+        assert_eq!(cpu.regs.a & 0x80, 0);
+        assert_eq!(nmi_follows(&mut cpu), false);
+        assert_eq!(cpu.mem_map.ppu.regs.ppu_status & 0x80, 0);
+    }
+
+    #[test]
+    fn status_read_suppresses_nmi() {
+        for dot in 1..3 {
+            let mut cpu = read_status_at(241, dot);
+            assert_eq!(cpu.regs.a & 0x80, 0x80);
+            assert_eq!(nmi_follows(&mut cpu), false);
+        }
+    }
+
+    #[test]
+    fn status_read_after_nmi() {
+        let mut cpu = read_status_at(241, 6);
+        assert_eq!(cpu.regs.a & 0x80, 0x80);
+        assert_eq!(nmi_follows(&mut cpu), true);
+    }
+
+    #[test]
+    fn toggling_nmi_during_vblank() {
+        let mut rom = setup_rom();
+        rom.prg_rom[0xFFFA & 0x3FFF] = 0x00;
+        rom.prg_rom[0xFFFB & 0x3FFF] = 0x03;
+        let mut cpu = CPU::new(setup_ppu(), RAM::new(), Box::new(NROM::new(rom)));
+        cpu.regs.pc = 0x200;
+        run_until_vblank(&mut cpu);
+
+        // INC $10, RTI
+        cpu.mem_map.ram.mem[0x300..0x303].copy_from_slice(&[0xE6, 0x10, 0x40]);
+        cpu.mem_map.ram.mem[0x210..0x232].copy_from_slice(&[
+            // Enabling twice only gives one NMI, enabling again after
+            // turning it off gives another
+            0xA9, 0x80, 0x8D, 0x00, 0x20, 0x8D, 0x00, 0x20,
+            0xA9, 0x00, 0x8D, 0x00, 0x20, 0xA9, 0x80, 0x8D, 0x00, 0x20,
+            // Not once the flag's been read though
+            0xAD, 0x02, 0x20, 0xA9, 0x00, 0x8D, 0x00, 0x20,
+            0xA9, 0x80, 0x8D, 0x00, 0x20,
+            0x4C, 0x2F, 0x02
+        ]);
+        cpu.regs.pc = 0x210;
+        for _ in 0..30 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.regs.pc, 0x22F);
+        assert_eq!(cpu.mem_map.ram.mem[0x10], 2);
+    }
+
+    // Where the PPU is in its frame after the CPU has run a JMP loop for a
+    // little over 6 frames
+    fn ppu_position_after_frames(ppu_mask: u8) -> (u64, u16, u16) {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ppu.regs.ppu_mask = ppu_mask;
+        cpu.mem_map.ram.mem[0x200..0x203].copy_from_slice(&[0x4C, 0x00, 0x02]);
+        while cpu.cycles < 180_000 {
+            cpu.step().unwrap();
+        }
+        cpu.mem_map.catch_up();
+        let ppu = &cpu.mem_map.ppu;
+        (ppu.frame, ppu.scanline, ppu.dot)
+    }
+
+    #[test]
+    fn odd_frames_skip_a_dot_while_rendering() {
+        let (frame, scanline, dot) = ppu_position_after_frames(0x00);
+        assert_eq!(frame, 6);
+        assert_eq!(scanline as u32 * 341 + dot as u32, 180_000 * 3 - 6 * 89342);
+
+        // Frames 1, 3 and 5 were a dot short
+        let (frame, rendering_scanline, rendering_dot) = ppu_position_after_frames(0x08);
+        assert_eq!(frame, 6);
+        assert_eq!(rendering_scanline as u32 * 341 + rendering_dot as u32,
+                   scanline as u32 * 341 + dot as u32 + 3);
+    }
+
+    #[test]
+    fn irq_masked_by_interrupt_flag() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ram.mem[0x200] = 0xEA;
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap(), 2);
+        assert_eq!(cpu.regs.pc, 0x201);
+    }
+
+    #[test]
+    fn irq_delayed_after_cli() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ram.mem[0x200] = 0x58;
+        cpu.mem_map.ram.mem[0x201] = 0xEA;
+        cpu.set_irq(true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0x202);
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.regs.pc, 0xA000);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FB] & (F_BREAK | F_INTERRUPT), 0);
+    }
+
+    #[test]
+    fn irq_taken_after_sei() {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ram.mem[0x200] = 0x58;
+        cpu.mem_map.ram.mem[0x201] = 0x78;
+        cpu.step().unwrap();
+        cpu.set_irq(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.regs.pc, 0xA000);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FB] & F_INTERRUPT, F_INTERRUPT);
+    }
+
+    // Runs a BRK at $200 with the PPU moved ahead so vblank starts on the
+    // given cycle of it
+    fn brk_with_vblank_on_cycle(cycle: u32) -> CPU {
+        let mut cpu = setup_interrupt_cpu();
+        cpu.mem_map.ppu.regs.ppu_ctrl = 0x80;
+        for _ in 0..(241 * 341 + 2 - 3 * cycle) {
+            cpu.mem_map.ppu.tick(&*cpu.mem_map.mapper);
+        }
+        assert_eq!(cpu.step().unwrap(), 7);
+        cpu
+    }
+
+    #[test]
+    fn brk_hijacked_by_nmi() {
+        let mut cpu = brk_with_vblank_on_cycle(4);
+        assert_eq!(cpu.regs.pc, 0x9000);
+        assert_eq!(cpu.mem_map.ram.mem[0x1FB] & F_BREAK, F_BREAK);
+
+        // Only the one interrupt, the BRK at $9000 goes through
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc, 0xA000);
+    }
+
+    #[test]
+    fn late_nmi_taken_after_brk() {
+        let mut cpu = brk_with_vblank_on_cycle(5);
+        assert_eq!(cpu.regs.pc, 0xA000);
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.regs.pc, 0x9000);
+    }
+
+    #[test]
+    fn kil_jams_cpu() {
+        for opcode in [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2].iter() {
+            let mut cpu = setup_cpu();
+            cpu.regs.pc = 0x200;
+            cpu.mem_map.ram.mem[0x200] = *opcode;
+            cpu.step().unwrap();
+            assert_eq!(cpu.is_jammed(), true);
+            assert_eq!(cpu.regs.pc, 0x200);
+        }
+    }
+
+    #[test]
+    fn jammed_cpu_stops_fetching() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0x02;
+        cpu.step().unwrap();
+        let cycles = cpu.cycles;
+        cpu.set_nmi(true);
+        assert_eq!(cpu.step().unwrap(), 0);
+        assert_eq!(cpu.regs.pc, 0x200);
+        assert_eq!(cpu.cycles, cycles);
+    }
+
+    #[test]
+    fn reset_clears_jam() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200] = 0x02;
+        cpu.step().unwrap();
+        cpu.reset();
+        assert_eq!(cpu.is_jammed(), false);
+    }
+
+    #[test]
+    fn immediate_store_is_an_error() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.regs.a = 0xDE;
+        cpu.sta(ImmediateAddressingMode);
+        cpu.mem_map.ram.mem[0x200] = 0xEA;
+        cpu.mem_map.ram.mem[0x201] = 0xEA;
+        match cpu.step() {
+            Err(EmuError::IllegalWrite { val, regs }) => {
+                assert_eq!(val, 0xDE);
+                assert_eq!(regs.a, 0xDE);
+            },
+            _ => panic!("Expected an illegal write error")
+        }
+        assert_eq!(cpu.step().unwrap(), 2);
+    }
+
+    #[test]
+    fn opcode_table_metadata() {
+        let lda = &OPCODES[0xBD];
+        assert_eq!(lda.mnemonic, "LDA");
+        assert_eq!(lda.mode, Mode::AbsoluteX);
+        assert_eq!(lda.bytes, 3);
+        assert_eq!(lda.cycles, 4);
+        assert_eq!(lda.page_cross_cycles, 1);
+        assert_eq!(lda.official, true);
+
+        let sbc = &OPCODES[0xEB];
+        assert_eq!(sbc.mnemonic, "SBC");
+        assert_eq!(sbc.mode, Mode::Immediate);
+        assert_eq!(sbc.official, false);
+    }
+
+    #[test]
+    fn opcode_table_official_count() {
+        assert_eq!(OPCODES.iter().filter(|op| op.official).count(), 151);
+    }
+
+    fn setup_dma_cpu(page: u8) -> CPU {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.mem_map.ram.mem[0x200..0x205].copy_from_slice(&[0xA9, page, 0x8D, 0x14, 0x40]);
+        cpu
+    }
+
+    fn run_dma(cpu: &mut CPU) {
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        while cpu.stall_cycles() > 0 {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn oam_dma_copies_page() {
+        let mut cpu = setup_dma_cpu(0x03);
+        for i in 0..0x100 {
+            cpu.mem_map.ram.mem[0x300 + i] = i as u8;
+        }
+        run_dma(&mut cpu);
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x00], 0x00);
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x7F], 0x7F);
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0xFF], 0xFF);
+    }
+
+    #[test]
+    fn oam_dma_from_prg_ram() {
+        let mut cpu = setup_dma_cpu(0x60);
+        cpu.store_byte(0x6010, 0xAB);
+        run_dma(&mut cpu);
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x10], 0xAB);
+    }
+
+    #[test]
+    fn oam_dma_stalls_cpu() {
+        let mut cpu = setup_dma_cpu(0x03);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycles, 6);
+        assert_eq!(cpu.stall_cycles(), 513);
+        for _ in 0..513 {
+            assert_eq!(cpu.step().unwrap(), 1);
+        }
+        assert_eq!(cpu.regs.pc, 0x205);
+        assert_eq!(cpu.cycles, 519);
+    }
+
+    #[test]
+    fn oam_dma_one_byte_every_other_cycle() {
+        let mut cpu = setup_dma_cpu(0x03);
+        cpu.mem_map.ram.mem[0x300..0x302].copy_from_slice(&[0xAA, 0xBB]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        // Halt, read $300, write, read $301
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x00], 0xAA);
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x01], 0x00);
+        assert_eq!(cpu.mem_map.ppu.dot, 10 * 3);
+        cpu.step().unwrap();
+        assert_eq!(cpu.mem_map.ppu.oam.oam[0x01], 0xBB);
+    }
+
+    #[test]
+    fn oam_dma_odd_cycle_stall() {
+        let mut cpu = setup_dma_cpu(0x03);
+        cpu.cycles = 1;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.stall_cycles(), 514);
+    }
+
+    #[test]
+    fn dmc_stall() {
+        let mut cpu = setup_cpu();
+        cpu.regs.pc = 0x200;
+        cpu.stall(4);
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.regs.pc, 0x200);
+        assert_eq!(cpu.cycles, 4);
+    }
+
+    // Logs every access to cartridge space so tests can check the exact
+    // sequence of bus cycles an instruction makes
+    #[derive(Clone)]
+    struct RecordingMapper {
+        rom: Rom,
+        log: Arc<Mutex<Vec<(char, u16, u8)>>>
+    }
+
+    impl Mapper for RecordingMapper {
+        fn type_of(&self) -> String { "Recording".to_string() }
+        fn get_rom(&self) -> &Rom { &self.rom }
+        fn load_prg_byte(&self, addr: u16) -> u8 {
+            self.log.lock().unwrap().push(('R', addr, 0));
+            0
+        }
+        fn load_chr_byte(&self, _addr: u16) -> u8 { 0 }
+        fn store_prg_byte(&mut self, addr: u16, val: u8) {
+            self.log.lock().unwrap().push(('W', addr, val));
+        }
+        fn store_chr_byte(&mut self, _addr: u16, _val: u8) {}
+    }
+
+    fn run_recorded(program: &[u8], x: u8) -> Vec<(char, u16, u8)> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mapper = RecordingMapper { rom: setup_rom(), log: log.clone() };
+        let mut cpu = CPU::new(setup_ppu(), RAM::new(), Box::new(mapper));
+        cpu.regs.pc = 0x200;
+        cpu.regs.x = x;
+        cpu.mem_map.ram.mem[0x200..0x200 + program.len()].copy_from_slice(program);
+        cpu.step().unwrap();
+        let result = log.lock().unwrap().clone();
+        result
+    }
+
+    #[test]
+    fn rmw_writes_old_value_first() {
+        let log = run_recorded(&[0xEE, 0x10, 0x60], 0);
+        assert_eq!(log, vec![('R', 0x6010, 0), ('W', 0x6010, 0), ('W', 0x6010, 1)]);
+    }
+
+    #[test]
+    fn rmw_indexed_reads_uncarried_address() {
+        let log = run_recorded(&[0xFE, 0x00, 0x60], 0x10);
+        assert_eq!(log, vec![('R', 0x6010, 0), ('R', 0x6010, 0), ('W', 0x6010, 0), ('W', 0x6010, 1)]);
+    }
+
+    #[test]
+    fn indexed_read_page_cross_dummy_read() {
+        let log = run_recorded(&[0xBD, 0xF0, 0x60], 0x20);
+        assert_eq!(log, vec![('R', 0x6010, 0), ('R', 0x6110, 0)]);
+    }
+
+    #[test]
+    fn indexed_read_same_page_no_dummy_read() {
+        let log = run_recorded(&[0xBD, 0x00, 0x60], 0x10);
+        assert_eq!(log, vec![('R', 0x6010, 0)]);
+    }
+
+    #[test]
+    fn indexed_store_always_dummy_reads() {
+        let log = run_recorded(&[0x9D, 0x00, 0x60], 0x10);
+        assert_eq!(log, vec![('R', 0x6010, 0), ('W', 0x6010, 0)]);
+    }
 }
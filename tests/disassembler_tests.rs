@@ -0,0 +1,89 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod disassembler_tests {
+    use mr_cool_nes::core::cpu::Mode;
+    use mr_cool_nes::core::disassembler::{disassemble, disassemble_prg};
+    use mr_cool_nes::core::memory::RAM;
+    use mr_cool_nes::core::rom::{INesHeader, Rom};
+
+    fn setup_ram(program: &[u8]) -> RAM {
+        let mut ram = RAM::new();
+        ram.mem[0x200..0x200 + program.len()].copy_from_slice(program);
+        ram
+    }
+
+    fn setup_rom(prg_rom: Vec<u8>) -> Rom {
+        Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom,
+            chr_rom: vec![0; 8192]
+        }
+    }
+
+    #[test]
+    fn disassemble_sequence() {
+        let ram = setup_ram(&[0xA9, 0x10, 0x8D, 0x00, 0x03, 0xD0, 0xF9, 0x04, 0xA9]);
+        let instructions = disassemble(&ram, 0x200, 4);
+        assert_eq!(instructions.len(), 4);
+
+        assert_eq!(instructions[0].mnemonic, "LDA");
+        assert_eq!(instructions[0].mode, Mode::Immediate);
+        assert_eq!(instructions[0].operand, "#$10");
+        assert_eq!(instructions[0].target, None);
+
+        assert_eq!(instructions[1].addr, 0x202);
+        assert_eq!(instructions[1].operand, "$0300");
+        assert_eq!(instructions[1].target, Some(0x300));
+
+        assert_eq!(instructions[2].mnemonic, "BNE");
+        assert_eq!(instructions[2].target, Some(0x200));
+
+        assert_eq!(instructions[3].official, false);
+        assert_eq!(format!("{}", instructions[3]), "0207  04 A9    *NOP $A9");
+    }
+
+    #[test]
+    fn disassemble_display() {
+        let ram = setup_ram(&[0x4C, 0xF5, 0xC5, 0xEA]);
+        let instructions = disassemble(&ram, 0x200, 2);
+        assert_eq!(format!("{}", instructions[0]), "0200  4C F5 C5  JMP $C5F5");
+        assert_eq!(format!("{}", instructions[1]), "0203  EA        NOP");
+    }
+
+    #[test]
+    fn disassemble_indirect_jump_target() {
+        let mut ram = setup_ram(&[0x6C, 0xFF, 0x02]);
+        ram.mem[0x2FF] = 0x34;
+        ram.mem[0x200] = 0x6C;
+        ram.mem[0x300] = 0x99;
+        let instructions = disassemble(&ram, 0x200, 1);
+        assert_eq!(instructions[0].operand, "($02FF)");
+        assert_eq!(instructions[0].target, Some(0x6C34));
+    }
+
+    #[test]
+    fn disassemble_prg_labels_vectors() {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[0x3FFA..].copy_from_slice(&[0x02, 0xC0, 0x00, 0xC0, 0x01, 0xC0]);
+        let listing = disassemble_prg(&setup_rom(prg_rom));
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines[0], "; bank 0 at $C000");
+        assert_eq!(lines[1], "reset:");
+        assert_eq!(lines[2], "C000  EA        NOP");
+        assert_eq!(lines[3], "irq:");
+        assert_eq!(lines[5], "nmi:");
+        assert!(listing.contains("FFFC  .word $C000 ; reset\n"));
+    }
+}
@@ -0,0 +1,117 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod hooks_tests {
+    use mr_cool_nes::core::cpu::CPU;
+    use mr_cool_nes::core::hooks::Access;
+    use mr_cool_nes::core::mapper::NROM;
+    use mr_cool_nes::core::memory::{Memory, RAM};
+    use mr_cool_nes::core::ppu::PPU;
+    use mr_cool_nes::core::rom::{INesHeader, Rom};
+    use std::sync::{Arc, Mutex};
+
+    fn setup_rom() -> Rom {
+        Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom: vec![0; 16384],
+            chr_rom: vec![0; 8192]
+        }
+    }
+
+    fn setup_cpu() -> CPU {
+        CPU::new(PPU::new(), RAM::new(), Box::new(NROM::new(setup_rom())))
+    }
+
+    fn record(log: &Arc<Mutex<Vec<(u16, u8)>>>) -> impl FnMut(u16, u8) -> Option<u8> + Send + 'static {
+        let log = log.clone();
+        move |addr, val| {
+            log.lock().unwrap().push((addr, val));
+            None
+        }
+    }
+
+    #[test]
+    fn read_hook_sees_value() {
+        let mut cpu = setup_cpu();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        cpu.mem_map.hooks.add(Access::Read, 0x0010..=0x0010, record(&log));
+        cpu.mem_map.ram.mem[0x10] = 0x42;
+
+        cpu.mem_map.load_byte(0x0010);
+        cpu.mem_map.load_byte(0x0011);
+        assert_eq!(*log.lock().unwrap(), vec![(0x0010, 0x42)]);
+    }
+
+    #[test]
+    fn read_hook_overrides_value() {
+        let mut cpu = setup_cpu();
+        cpu.mem_map.hooks.add(Access::Read, 0x0000..=0x07FF, |_, _| Some(0x99));
+        cpu.mem_map.ram.mem[0x10] = 0x42;
+
+        assert_eq!(cpu.mem_map.load_byte(0x0010), 0x99);
+        assert_eq!(cpu.mem_map.ram.mem[0x10], 0x42);
+        assert_eq!(cpu.mem_map.load_byte(0x0800), 0x00);
+    }
+
+    #[test]
+    fn write_hook_overrides_value() {
+        let mut cpu = setup_cpu();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        cpu.mem_map.hooks.add(Access::Write, 0x0020..=0x002F, record(&log));
+        cpu.mem_map.hooks.add(Access::Write, 0x0020..=0x002F, |_, val| Some(val + 1));
+
+        cpu.mem_map.store_byte(0x0025, 0x10);
+        assert_eq!(cpu.mem_map.ram.mem[0x25], 0x11);
+        assert_eq!(*log.lock().unwrap(), vec![(0x0025, 0x10)]);
+    }
+
+    #[test]
+    fn fetch_hook_sees_opcodes_only() {
+        let mut cpu = setup_cpu();
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        cpu.mem_map.hooks.add(Access::Fetch, 0x0200..=0x02FF, record(&fetches));
+        cpu.mem_map.hooks.add(Access::Read, 0x0200..=0x02FF, record(&reads));
+
+        // NOP, LDA $0210
+        cpu.mem_map.store_byte(0x0200, 0xEA);
+        cpu.mem_map.store_byte(0x0201, 0xAD);
+        cpu.mem_map.store_byte(0x0202, 0x10);
+        cpu.mem_map.store_byte(0x0203, 0x02);
+        cpu.regs.pc = 0x0200;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        assert_eq!(*fetches.lock().unwrap(), vec![(0x0200, 0xEA), (0x0201, 0xAD)]);
+        assert_eq!(*reads.lock().unwrap(), vec![(0x0201, 0xAD), (0x0202, 0x10), (0x0203, 0x02), (0x0210, 0x00)]);
+    }
+
+    #[test]
+    fn remove_hook() {
+        let mut cpu = setup_cpu();
+        let id = cpu.mem_map.hooks.add(Access::Read, 0x0000..=0xFFFF, |_, _| Some(0x99));
+        assert_eq!(cpu.mem_map.hooks.remove(id), true);
+        assert_eq!(cpu.mem_map.hooks.remove(id), false);
+        assert_eq!(cpu.mem_map.hooks.is_empty(), true);
+        assert_eq!(cpu.mem_map.load_byte(0x0010), 0x00);
+    }
+
+    #[test]
+    fn clone_drops_hooks() {
+        let mut cpu = setup_cpu();
+        cpu.mem_map.hooks.add(Access::Read, 0x0000..=0xFFFF, |_, _| Some(0x99));
+        let mut copy = cpu.clone();
+        assert_eq!(copy.mem_map.hooks.is_empty(), true);
+        assert_eq!(copy.mem_map.load_byte(0x0010), 0x00);
+    }
+}
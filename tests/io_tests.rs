@@ -0,0 +1,223 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod io_tests {
+    use mr_cool_nes::core::controller::{BUTTON_A, BUTTON_START, BUTTON_RIGHT};
+    use mr_cool_nes::core::mapper::NROM;
+    use mr_cool_nes::core::memory::{CPUMemoryMap, Memory, RAM};
+    use mr_cool_nes::core::ppu::PPU;
+    use mr_cool_nes::core::rom::{INesHeader, Rom};
+
+    fn setup_rom() -> Rom {
+        Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom: vec![0; 16384],
+            chr_rom: vec![0; 8192]
+        }
+    }
+
+    fn setup_bus() -> CPUMemoryMap {
+        CPUMemoryMap::new(PPU::new(), RAM::new(), Box::new(NROM::new(setup_rom())))
+    }
+
+    fn run_cycles(bus: &mut CPUMemoryMap, cycles: u32) {
+        for _ in 0..cycles {
+            bus.tick();
+        }
+        bus.catch_up();
+    }
+
+    #[test]
+    fn controller_reads_buttons_in_order() {
+        let mut bus = setup_bus();
+        bus.controllers[0].set_button(BUTTON_A, true);
+        bus.controllers[0].set_button(BUTTON_START, true);
+        bus.controllers[0].set_button(BUTTON_RIGHT, true);
+        bus.store_byte(0x4016, 1);
+        bus.store_byte(0x4016, 0);
+
+        let bits: Vec<u8> = (0..10).map(|_| bus.load_byte(0x4016) & 1).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn controller_strobe_reloads() {
+        let mut bus = setup_bus();
+        bus.controllers[1].set_button(BUTTON_A, true);
+        bus.store_byte(0x4016, 1);
+        assert_eq!(bus.load_byte(0x4017) & 1, 1);
+        assert_eq!(bus.load_byte(0x4017) & 1, 1);
+        assert_eq!(bus.load_byte(0x4016) & 1, 0);
+    }
+
+    #[test]
+    fn apu_length_counter_status() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x4015, 0x01);
+        bus.store_byte(0x4003, 0x08);
+        assert_eq!(bus.load_byte(0x4015) & 0x0F, 0x01);
+
+        bus.store_byte(0x4015, 0x00);
+        assert_eq!(bus.load_byte(0x4015) & 0x0F, 0x00);
+    }
+
+    #[test]
+    fn apu_length_counter_ignored_while_disabled() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x400F, 0x08);
+        assert_eq!(bus.load_byte(0x4015) & 0x08, 0x00);
+    }
+
+    #[test]
+    fn apu_length_counter_runs_out() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x4015, 0x02);
+        // Length index 3 loads a count of 2
+        bus.store_byte(0x4007, 0x03 << 3);
+        run_cycles(&mut bus, 14913);
+        assert_eq!(bus.load_byte(0x4015) & 0x02, 0x02);
+        run_cycles(&mut bus, 29829 - 14913);
+        assert_eq!(bus.load_byte(0x4015) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn apu_frame_irq() {
+        let mut bus = setup_bus();
+        run_cycles(&mut bus, 29828);
+        assert_eq!(bus.irq(), false);
+        run_cycles(&mut bus, 1);
+        assert_eq!(bus.irq(), true);
+        assert_eq!(bus.load_byte(0x4015) & 0x40, 0x40);
+        assert_eq!(bus.irq(), false);
+    }
+
+    #[test]
+    fn apu_frame_irq_inhibit() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x4017, 0x40);
+        run_cycles(&mut bus, 30000);
+        assert_eq!(bus.irq(), false);
+
+        bus.store_byte(0x4017, 0x80);
+        run_cycles(&mut bus, 40000);
+        assert_eq!(bus.irq(), false);
+    }
+
+    #[test]
+    fn expansion_area_goes_to_mapper() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x6000, 0xAB);
+        assert_eq!(bus.load_byte(0x6000), 0xAB);
+        bus.store_byte(0x5000, 0xCD);
+        assert_eq!(bus.load_byte(0x6000), 0xAB);
+    }
+
+    #[test]
+    fn unmapped_reads_open_bus() {
+        let mut bus = setup_bus();
+        bus.ram.store_byte(0x0010, 0x5A);
+        bus.load_byte(0x0010);
+        assert_eq!(bus.load_byte(0x4000), 0x5A);
+        assert_eq!(bus.load_byte(0x4018), 0x5A);
+        assert_eq!(bus.load_byte(0x5000), 0x5A);
+
+        bus.store_byte(0x4000, 0x33);
+        assert_eq!(bus.load_byte(0x401F), 0x33);
+    }
+
+    #[test]
+    fn apu_status_open_bus_bit() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x4010, 0xFF);
+        assert_eq!(bus.load_byte(0x4015), 0x20);
+        bus.store_byte(0x4010, 0x00);
+        assert_eq!(bus.load_byte(0x4015), 0x00);
+    }
+
+    #[test]
+    fn controller_open_bus_bits() {
+        let mut bus = setup_bus();
+        bus.controllers[0].set_button(BUTTON_A, true);
+        bus.store_byte(0x4016, 1);
+        bus.store_byte(0x4016, 0);
+        bus.store_byte(0x4000, 0x40);
+        assert_eq!(bus.load_byte(0x4016), 0x41);
+    }
+
+    #[test]
+    fn ppu_write_only_reads_ppu_latch() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x2003, 0x12);
+        bus.store_byte(0x0000, 0x34);
+        assert_eq!(bus.load_byte(0x2000), 0x12);
+    }
+
+    #[test]
+    fn peek_leaves_frame_irq() {
+        let mut bus = setup_bus();
+        run_cycles(&mut bus, 29829);
+        assert_eq!(bus.peek(0x4015) & 0x40, 0x40);
+        assert_eq!(bus.irq(), true);
+        assert_eq!(bus.load_byte(0x4015) & 0x40, 0x40);
+        assert_eq!(bus.peek(0x4015) & 0x40, 0x00);
+    }
+
+    #[test]
+    fn peek_leaves_controller_shift() {
+        let mut bus = setup_bus();
+        bus.controllers[0].set_button(BUTTON_A, true);
+        bus.store_byte(0x4016, 1);
+        bus.store_byte(0x4016, 0);
+        assert_eq!(bus.peek(0x4016) & 1, 1);
+        assert_eq!(bus.peek(0x4016) & 1, 1);
+        assert_eq!(bus.load_byte(0x4016) & 1, 1);
+        assert_eq!(bus.peek(0x4016) & 1, 0);
+    }
+
+    #[test]
+    fn peek_leaves_open_bus() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x2000, 0x12);
+        bus.ram.store_byte(0x0010, 0x34);
+        bus.store_byte(0x4000, 0x56);
+        assert_eq!(bus.peek(0x0010), 0x34);
+        assert_eq!(bus.peek(0x2002), 0x12);
+        assert_eq!(bus.peek(0x5000), 0x56);
+        assert_eq!(bus.open_bus, 0x56);
+        assert_eq!(bus.ppu.open_bus, 0x12);
+    }
+
+    #[test]
+    fn peek_doesnt_tick() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x6000, 0xAB);
+        assert_eq!(bus.peek(0x6000), 0xAB);
+        assert_eq!(bus.peek_word(0xFFFC), 0x0000);
+        assert_eq!(bus.scheduler.cpu_cycles(), 0);
+    }
+
+    #[test]
+    fn ppu_data_through_bus() {
+        let mut bus = setup_bus();
+        bus.store_byte(0x2006, 0x23);
+        bus.store_byte(0x2006, 0xC0);
+        bus.store_byte(0x2007, 0x55);
+        assert_eq!(bus.ppu.vram.nametables[0x3C0], 0x55);
+
+        bus.store_byte(0x2006, 0x23);
+        bus.store_byte(0x2006, 0xC0);
+        bus.load_byte(0x2007);
+        assert_eq!(bus.peek(0x2007), 0x55);
+        assert_eq!(bus.load_byte(0x2007), 0x55);
+    }
+}
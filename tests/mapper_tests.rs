@@ -2,8 +2,9 @@ extern crate mr_cool_nes;
 
 #[cfg(test)]
 mod mapper_tests {
-    use mr_cool_nes::core::mapper::{select_mapper};
-    use mr_cool_nes::core::rom::{INesHeader, Rom};
+    use mr_cool_nes::core::error::EmuError;
+    use mr_cool_nes::core::mapper::{select_mapper, Mapper};
+    use mr_cool_nes::core::rom::{INesHeader, Mirroring, Rom};
     
     fn setup_rom() -> Rom {
         Rom {
@@ -26,7 +27,7 @@ mod mapper_tests {
     #[test]
     fn select_mapper_nrom() {
         let rom = setup_rom();
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         assert_eq!(mapper.type_of(), "NROM");
     }
 
@@ -34,23 +35,53 @@ mod mapper_tests {
     fn select_mapper_sxrom() {
         let mut rom = setup_rom();
         rom.header.flags_6 = 0x10;
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         assert_eq!(mapper.type_of(), "SxROM");
     }
 
     #[test]
-    #[should_panic]
-    fn select_mapper_panic() {
+    fn select_mapper_unsupported() {
         let mut rom = setup_rom();
         rom.header.flags_6 = 0xFF;
-        select_mapper(rom);
+        match select_mapper(rom) {
+            Err(EmuError::UnsupportedMapper(number)) => assert_eq!(number, 0x0F),
+            _ => panic!("Expected an unsupported mapper error")
+        }
+    }
+
+    #[test]
+    fn select_mapper_partial_prg_bank() {
+        let mut rom = setup_rom();
+        rom.prg_rom = vec![0; 0x1000];
+        match select_mapper(rom) {
+            Err(EmuError::InvalidRom(_)) => (),
+            _ => panic!("Expected an invalid rom error")
+        }
+    }
+
+    #[test]
+    fn cnrom_chr_banks() {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x30;
+        rom.prg_rom[0x0000] = 0xFF;
+        rom.prg_rom[0x0001] = 0x01;
+        rom.chr_rom = (0..4).flat_map(|bank| vec![bank; 0x2000]).collect();
+        let mut mapper = select_mapper(rom).unwrap();
+        assert_eq!(mapper.type_of(), "CNROM");
+        assert_eq!(mapper.load_chr_byte(0x1FFF), 0);
+        mapper.store_prg_byte(0x8000, 0x02);
+        assert_eq!(mapper.load_chr_byte(0x0000), 2);
+
+        // Bus conflict with the $01 in ROM
+        mapper.store_prg_byte(0xC001, 0x03);
+        assert_eq!(mapper.load_chr_byte(0x0000), 1);
     }
 
     #[test]
     fn nrom_load_prg_byte() {
         let mut rom = setup_rom();
         rom.prg_rom[0xDE] = 0xAD;
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         let byte = mapper.load_prg_byte(0x80DE);
         assert_eq!(byte, 0xAD);
     }
@@ -59,7 +90,7 @@ mod mapper_tests {
     fn nrom_load_chr_byte() {
         let mut rom = setup_rom();
         rom.chr_rom[0xDE] = 0xAD;
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         let byte = mapper.load_chr_byte(0xDE);
         assert_eq!(byte, 0xAD);
     }
@@ -68,7 +99,7 @@ mod mapper_tests {
     fn nrom_load_prg_byte_zero() {
         let mut rom = setup_rom();
         rom.prg_rom[0xDE] = 0xAD;
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         let byte = mapper.load_prg_byte(0xDE);
         assert_eq!(byte, 0x00);
     }
@@ -78,8 +109,115 @@ mod mapper_tests {
         let mut rom = setup_rom();
         rom.prg_rom = vec![0; 0x8000];
         rom.prg_rom[0xDE] = 0xAD;
-        let mapper = select_mapper(rom);
+        let mapper = select_mapper(rom).unwrap();
         let byte = mapper.load_prg_byte(0x80DE);
         assert_eq!(byte, 0xAD);
     }
+
+    #[test]
+    fn nrom_chr_ram() {
+        let mut rom = setup_rom();
+        rom.chr_rom = vec![];
+        let mut mapper = select_mapper(rom).unwrap();
+        mapper.store_chr_byte(0x1FDE, 0xAD);
+        assert_eq!(mapper.load_chr_byte(0x1FDE), 0xAD);
+    }
+
+    #[test]
+    fn mirroring_from_header() {
+        let mut rom = setup_rom();
+        assert_eq!(select_mapper(rom.clone()).unwrap().mirroring(), Mirroring::Horizontal);
+        rom.header.flags_6 = 0x01;
+        assert_eq!(select_mapper(rom.clone()).unwrap().mirroring(), Mirroring::Vertical);
+        rom.header.flags_6 = 0x09;
+        assert_eq!(select_mapper(rom).unwrap().mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn sxrom_mirroring_from_control() {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x11;
+        let mut mapper = select_mapper(rom).unwrap();
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+        write_serial(&mut mapper, 0x8000, 0x0E);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        write_serial(&mut mapper, 0x9FFF, 0x0F);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        write_serial(&mut mapper, 0xE000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    fn write_serial(mapper: &mut Box<dyn Mapper>, addr: u16, val: u8) {
+        for bit in 0..5 {
+            mapper.store_prg_byte(addr, (val >> bit) & 0x01);
+        }
+    }
+
+    #[test]
+    fn sxrom_reset_restarts_serial_write() {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x10;
+        let mut mapper = select_mapper(rom).unwrap();
+        mapper.store_prg_byte(0x8000, 0x01);
+        mapper.store_prg_byte(0x8000, 0x80);
+        write_serial(&mut mapper, 0x8000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    fn setup_sxrom() -> Box<dyn Mapper> {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x10;
+        rom.prg_rom = (0..8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        rom.chr_rom = (0..8).flat_map(|bank| vec![bank; 0x1000]).collect();
+        select_mapper(rom).unwrap()
+    }
+
+    #[test]
+    fn sxrom_prg_banks() {
+        let mut mapper = setup_sxrom();
+        assert_eq!(mapper.load_prg_byte(0x8000), 0);
+        assert_eq!(mapper.load_prg_byte(0xC000), 7);
+        write_serial(&mut mapper, 0xE000, 0x03);
+        assert_eq!(mapper.load_prg_byte(0xBFFF), 3);
+        assert_eq!(mapper.load_prg_byte(0xFFFF), 7);
+
+        // First bank fixed at $8000
+        write_serial(&mut mapper, 0x8000, 0x08);
+        assert_eq!(mapper.load_prg_byte(0x8000), 0);
+        assert_eq!(mapper.load_prg_byte(0xC000), 3);
+
+        // 32 KB at once, the low bit is ignored
+        write_serial(&mut mapper, 0x8000, 0x00);
+        assert_eq!(mapper.load_prg_byte(0x8000), 2);
+        assert_eq!(mapper.load_prg_byte(0xC000), 3);
+
+        // Past the end wraps around
+        write_serial(&mut mapper, 0xE000, 0x0C);
+        assert_eq!(mapper.load_prg_byte(0x8000), 4);
+    }
+
+    #[test]
+    fn sxrom_chr_banks() {
+        let mut mapper = setup_sxrom();
+        write_serial(&mut mapper, 0xA000, 0x05);
+        write_serial(&mut mapper, 0xC000, 0x02);
+        assert_eq!(mapper.load_chr_byte(0x0000), 4);
+        assert_eq!(mapper.load_chr_byte(0x1000), 5);
+
+        write_serial(&mut mapper, 0x8000, 0x1C);
+        assert_eq!(mapper.load_chr_byte(0x0000), 5);
+        assert_eq!(mapper.load_chr_byte(0x1FFF), 2);
+    }
+
+    #[test]
+    fn sxrom_prg_and_chr_ram() {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x10;
+        rom.chr_rom = vec![];
+        let mut mapper = select_mapper(rom).unwrap();
+        mapper.store_prg_byte(0x6123, 0xAD);
+        mapper.store_chr_byte(0x1FDE, 0xDE);
+        assert_eq!(mapper.load_prg_byte(0x6123), 0xAD);
+        assert_eq!(mapper.load_chr_byte(0x1FDE), 0xDE);
+    }
 }
@@ -74,4 +74,34 @@ mod memory_tests {
         let w = ram.load_word_zeropage_wraparound(0xFF);
         assert_eq!(w, 0xDEAD);
     }
+
+    #[test]
+    fn ram_init_zeros() {
+        let ram = memory::RAM::with_init(memory::RamInit::Zeros);
+        assert!(ram.mem.iter().all(|&b| b == 0x00));
+    }
+
+    #[test]
+    fn ram_init_ones() {
+        let ram = memory::RAM::with_init(memory::RamInit::Ones);
+        assert!(ram.mem.iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn ram_init_pattern() {
+        let ram = memory::RAM::with_init(memory::RamInit::Pattern);
+        assert_eq!(ram.mem[0..8], [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(ram.mem[0x7F8..0x800], [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn ram_init_random_is_seeded() {
+        let a = memory::RAM::with_init(memory::RamInit::Random(1));
+        let b = memory::RAM::with_init(memory::RamInit::Random(1));
+        let c = memory::RAM::with_init(memory::RamInit::Random(2));
+        let zero = memory::RAM::with_init(memory::RamInit::Random(0));
+        assert_eq!(a.mem[..], b.mem[..]);
+        assert!(a.mem[..] != c.mem[..]);
+        assert!(zero.mem.iter().any(|&b| b != 0));
+    }
 }
@@ -0,0 +1,143 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod nes_tests {
+    use mr_cool_nes::core::memory::RamInit;
+    use mr_cool_nes::core::nes::{Event, NES};
+    use mr_cool_nes::core::rom::Rom;
+    use mr_cool_nes::core::scheduler::Region;
+    use std::thread;
+
+    fn setup_nes() -> NES {
+        let rom = Rom::load(&"tests/roms/Galaxian.nes".to_owned()).unwrap();
+        NES::new(rom).unwrap()
+    }
+
+    #[test]
+    fn new_nes_is_reset() {
+        let nes = setup_nes();
+        assert_eq!(nes.cpu.regs.pc, 0xE020);
+        assert_eq!(nes.cpu.cycles, 7);
+    }
+
+    #[test]
+    fn power_on_registers() {
+        let nes = setup_nes();
+        assert_eq!(nes.cpu.regs.a, 0x00);
+        assert_eq!(nes.cpu.regs.x, 0x00);
+        assert_eq!(nes.cpu.regs.y, 0x00);
+        assert_eq!(nes.cpu.regs.s, 0xFD);
+        assert_eq!(nes.cpu.regs.p, 0x34);
+    }
+
+    #[test]
+    fn power_on_fills_ram() {
+        let rom = Rom::load(&"tests/roms/Galaxian.nes".to_owned()).unwrap();
+        let mut nes = NES::with_ram_init(rom, RamInit::Ones).unwrap();
+        assert!(nes.cpu.mem_map.ram.mem.iter().all(|&b| b == 0xFF));
+
+        nes.cpu.mem_map.ram.mem[0x10] = 0x12;
+        nes.power_on();
+        assert_eq!(nes.cpu.mem_map.ram.mem[0x10], 0xFF);
+        assert_eq!(nes.cpu.cycles, 7);
+    }
+
+    #[test]
+    fn reset_keeps_registers_and_ram() {
+        let mut nes = setup_nes();
+        nes.cpu.regs.a = 0x11;
+        nes.cpu.regs.x = 0x22;
+        nes.cpu.regs.y = 0x33;
+        nes.cpu.regs.s = 0x01;
+        nes.cpu.regs.p = 0x00;
+        nes.cpu.mem_map.ram.mem[0x10] = 0x12;
+        nes.cpu.mem_map.ppu.regs.ppu_ctrl = 0x80;
+        nes.reset();
+
+        assert_eq!(nes.cpu.regs.a, 0x11);
+        assert_eq!(nes.cpu.regs.x, 0x22);
+        assert_eq!(nes.cpu.regs.y, 0x33);
+        assert_eq!(nes.cpu.regs.s, 0xFE);
+        assert_eq!(nes.cpu.regs.p, 0x04);
+        assert_eq!(nes.cpu.regs.pc, 0xE020);
+        assert_eq!(nes.cpu.mem_map.ram.mem[0x10], 0x12);
+        assert_eq!(nes.cpu.mem_map.ppu.regs.ppu_ctrl, 0x00);
+    }
+
+    #[test]
+    fn nes_moves_between_threads() {
+        let mut nes = setup_nes();
+        let handle = thread::spawn(move || {
+            nes.cpu.step().unwrap();
+            nes
+        });
+        let nes = handle.join().unwrap();
+        assert_eq!(nes.cpu.regs.pc, 0xE021);
+    }
+
+    #[test]
+    fn cloned_nes_is_independent() {
+        let mut nes = setup_nes();
+        let saved = nes.clone();
+        nes.cpu.step().unwrap();
+        nes.cpu.mem_map.ram.mem[0x10] = 0xAB;
+
+        assert_eq!(saved.cpu.regs.pc, 0xE020);
+        assert_eq!(saved.cpu.mem_map.ram.mem[0x10], 0x00);
+        assert_eq!(nes.cpu.regs.pc, 0xE021);
+    }
+
+    #[test]
+    fn step_instruction_completes() {
+        let mut nes = setup_nes();
+        assert_eq!(nes.step_instruction().unwrap(), Event::Completed);
+        assert_eq!(nes.cpu.regs.pc, 0xE021);
+    }
+
+    #[test]
+    fn step_cycles_runs_whole_instructions() {
+        let mut nes = setup_nes();
+        assert_eq!(nes.step_cycles(3).unwrap(), Event::Completed);
+        assert_eq!(nes.cpu.cycles, 11);
+    }
+
+    #[test]
+    fn run_frame_stops_at_vblank() {
+        let mut nes = setup_nes();
+        assert_eq!(nes.run_frame().unwrap(), Event::FrameCompleted);
+        let first = nes.cpu.cycles;
+        assert!(first * 3 > 241 * 341);
+        assert_eq!(nes.run_frame().unwrap(), Event::FrameCompleted);
+        let frame = nes.cpu.cycles - first;
+        assert!((29775..=29786).contains(&frame));
+    }
+
+    #[test]
+    fn breakpoint_stops_execution() {
+        let mut nes = setup_nes();
+        nes.add_breakpoint(0xE022);
+        assert_eq!(nes.run_frame().unwrap(), Event::Breakpoint(0xE022));
+        assert_eq!(nes.cpu.regs.pc, 0xE022);
+        nes.remove_breakpoint(0xE022);
+        assert_eq!(nes.run_frame().unwrap(), Event::FrameCompleted);
+    }
+
+    #[test]
+    fn jam_stops_execution() {
+        let mut nes = setup_nes();
+        nes.cpu.regs.pc = 0x200;
+        nes.cpu.mem_map.ram.mem[0x200] = 0x02;
+        assert_eq!(nes.run_frame().unwrap(), Event::Jammed(0x200));
+    }
+
+    #[test]
+    fn pal_frame_is_longer() {
+        let mut nes = setup_nes();
+        nes.cpu.mem_map.set_region(Region::PAL);
+        nes.run_frame().unwrap();
+        let first = nes.cpu.cycles;
+        nes.run_frame().unwrap();
+        let frame = nes.cpu.cycles - first;
+        assert!((33240..=33255).contains(&frame));
+    }
+}
@@ -0,0 +1,79 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod palette_tests {
+    use mr_cool_nes::core::error::EmuError;
+    use mr_cool_nes::core::palette::Palette;
+    use std::env;
+    use std::fs;
+
+    fn write_pal(name: &str, bytes: &[u8]) -> String {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn ntsc_colors() {
+        let palette = Palette::ntsc();
+        assert_eq!(palette.rgb(0x00), [0x66, 0x66, 0x66]);
+        assert_eq!(palette.rgb(0x16), [0xB5, 0x31, 0x20]);
+        assert_eq!(palette.rgb(0x0F), [0x00, 0x00, 0x00]);
+        assert_eq!(palette.rgb(0x70), palette.rgb(0x30));
+    }
+
+    #[test]
+    fn ntsc_emphasis_darkens_other_channels() {
+        let palette = Palette::ntsc();
+        let [r, g, b] = palette.rgb(0x30);
+        let [er, eg, eb] = palette.emphasized(0x30, 0x01);
+        assert_eq!(er, r);
+        assert!(eg < g);
+        assert!(eb < b);
+        let [er, eg, eb] = palette.emphasized(0x30, 0x07);
+        assert!(er < r && eg < g && eb < b);
+    }
+
+    #[test]
+    fn pal_emphasis_swaps_red_and_green() {
+        let ntsc = Palette::ntsc();
+        let pal = Palette::pal_emphasis();
+        assert_eq!(pal.rgb(0x21), ntsc.rgb(0x21));
+        assert_eq!(pal.emphasized(0x21, 0x01), ntsc.emphasized(0x21, 0x02));
+        assert_eq!(pal.emphasized(0x21, 0x02), ntsc.emphasized(0x21, 0x01));
+        assert_eq!(pal.emphasized(0x21, 0x04), ntsc.emphasized(0x21, 0x04));
+    }
+
+    #[test]
+    fn rgb_2c03_colors() {
+        let palette = Palette::rgb_2c03();
+        assert_eq!(palette.rgb(0x20), [0xFF, 0xFF, 0xFF]);
+        assert_eq!(palette.rgb(0x16), [0xFF, 0x00, 0x00]);
+        assert_eq!(palette.emphasized(0x0F, 0x05), [0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn load_64_colors() {
+        let bytes: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let palette = Palette::load(&write_pal("mr_cool_nes_64.pal", &bytes)).unwrap();
+        assert_eq!(palette.rgb(0x01), [3, 4, 5]);
+        assert_eq!(palette.emphasized(0x01, 0x04)[2], 5);
+        assert!(palette.emphasized(0x01, 0x04)[0] < 3);
+    }
+
+    #[test]
+    fn load_512_colors() {
+        let bytes: Vec<u8> = (0..1536).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::load(&write_pal("mr_cool_nes_512.pal", &bytes)).unwrap();
+        assert_eq!(palette.rgb(0x01), [1, 1, 1]);
+        assert_eq!(palette.emphasized(0x01, 0x01), [65, 65, 65]);
+    }
+
+    #[test]
+    fn load_wrong_size() {
+        match Palette::load(&write_pal("mr_cool_nes_bad.pal", &[0; 100])) {
+            Err(EmuError::InvalidPalette(_)) => (),
+            _ => panic!("Expected an invalid palette error")
+        }
+    }
+}
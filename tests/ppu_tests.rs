@@ -3,7 +3,9 @@ extern crate mr_cool_nes;
 #[cfg(test)]
 mod ppu_tests {
     use mr_cool_nes::core::ppu::PPU;
-    use mr_cool_nes::core::memory::Memory;
+    use mr_cool_nes::core::mapper::{Mapper, NROM};
+    use mr_cool_nes::core::palette::Palette;
+    use mr_cool_nes::core::rom::{INesHeader, Mirroring, Rom};
 
     fn setup_ppu() -> PPU{
         PPU::new()
@@ -29,113 +31,673 @@ mod ppu_tests {
     #[test]
     fn store_byte_ppu_ctrl() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2000, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2000, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.ppu_ctrl, 0xDD);
     }
     
     #[test]
     fn store_byte_ppu_mask() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2001, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2001, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.ppu_mask, 0xDD);
     }
 
     #[test]
     fn store_byte_ppu_status() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2002, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2002, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.ppu_status, 0x0);
     }
 
     #[test]
     fn store_byte_ppu_oam_addr() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2003, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2003, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.oam_addr, 0xDD);
     }
 
     #[test]
     fn store_byte_ppu_oam_data() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2004, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2004, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.oam_data, 0xDD);
+        assert_eq!(ppu.oam.oam[0x00], 0xDD);
+        assert_eq!(ppu.regs.oam_addr, 0x01);
     }
 
     #[test]
     fn store_byte_ppu_ppu_scroll() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2005, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2005, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.ppu_scroll, 0xDD);
     }
 
     #[test]
     fn store_byte_ppu_ppu_addr() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2006, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2006, 0xDD, &mut mapper);
         assert_eq!(ppu.regs.ppu_addr, 0xDD);
     }
 
     #[test]
     fn store_byte_ppu_ppu_data() {
         let mut ppu = setup_ppu();
-        ppu.store_byte(0x2007, 0xDD);
-        assert_eq!(ppu.regs.ppu_data, 0xDD);
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2006, 0x20, &mut mapper);
+        ppu.write_register(0x2006, 0x05, &mut mapper);
+        ppu.write_register(0x2007, 0xDD, &mut mapper);
+        assert_eq!(ppu.vram.nametables[0x05], 0xDD);
+        assert_eq!(ppu.v, 0x2006);
     }
 
     #[test]
     fn load_byte_ppu_ctrl() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_ctrl = 0xDD;
-        assert_eq!(ppu.load_byte(0x2000), 0xDD);
+        assert_eq!(ppu.read_register(0x2000, &mapper), 0x00);
     }
 
     #[test]
     fn load_byte_ppu_mask() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_mask = 0xDD;
-        assert_eq!(ppu.load_byte(0x2001), 0xDD);
+        assert_eq!(ppu.read_register(0x2001, &mapper), 0x00);
     }
 
     #[test]
     fn load_byte_ppu_status() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_status = 0xDD;
-        assert_eq!(ppu.load_byte(0x2002), 0xDD);
+        assert_eq!(ppu.read_register(0x2002, &mapper), 0xC0);
+    }
+
+    #[test]
+    fn load_byte_write_only_open_bus() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2005, 0xA5, &mut mapper);
+        assert_eq!(ppu.read_register(0x2000, &mapper), 0xA5);
+        assert_eq!(ppu.read_register(0x2001, &mapper), 0xA5);
+        assert_eq!(ppu.read_register(0x2003, &mapper), 0xA5);
+        assert_eq!(ppu.read_register(0x2006, &mapper), 0xA5);
+    }
+
+    #[test]
+    fn load_byte_ppu_status_open_bus() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.regs.ppu_status = 0x80;
+        ppu.write_register(0x2000, 0x1F, &mut mapper);
+        assert_eq!(ppu.read_register(0x2002, &mapper), 0x9F);
+        // The status read drives the latch itself
+        ppu.regs.ppu_status = 0x00;
+        assert_eq!(ppu.read_register(0x2005, &mapper), 0x9F);
     }
 
     #[test]
     fn load_byte_oam_addr() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.oam_addr = 0xDD;
-        assert_eq!(ppu.load_byte(0x2003), 0x00);
+        assert_eq!(ppu.read_register(0x2003, &mapper), 0x00);
     }
 
     #[test]
     fn load_byte_oam_data() {
         let mut ppu = setup_ppu();
-        ppu.regs.oam_data = 0xDD;
-        assert_eq!(ppu.load_byte(0x2004), 0xDD);
+        let mapper = setup_mapper();
+        ppu.regs.oam_addr = 0x10;
+        ppu.oam.oam[0x10] = 0xDD;
+        assert_eq!(ppu.read_register(0x2004, &mapper), 0xDD);
     }
 
     #[test]
     fn load_byte_ppu_scroll() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_scroll = 0xDD;
-        assert_eq!(ppu.load_byte(0x2005), 0x00);
+        assert_eq!(ppu.read_register(0x2005, &mapper), 0x00);
     }
 
     #[test]
     fn load_byte_ppu_addr() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_addr = 0xDD;
-        assert_eq!(ppu.load_byte(0x2006), 0x00);
+        assert_eq!(ppu.read_register(0x2006, &mapper), 0x00);
     }
 
     #[test]
     fn load_byte_ppu_data() {
         let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
         ppu.regs.ppu_data = 0xDD;
-        assert_eq!(ppu.load_byte(0x2007), 0xDD);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0xDD);
+    }
+
+    // Tile 1 is solid color 1, tile 2 solid color 3, tile 3 only has its
+    // leftmost column set and tile 4 only its top row. Tile 0 is empty.
+    fn setup_mapper() -> NROM {
+        let mut chr_rom = vec![0; 8192];
+        for row in 0..8 {
+            chr_rom[0x10 + row] = 0xFF;
+            chr_rom[0x20 + row] = 0xFF;
+            chr_rom[0x28 + row] = 0xFF;
+            chr_rom[0x30 + row] = 0x80;
+        }
+        chr_rom[0x40] = 0xFF;
+        NROM::new(Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom: vec![0; 16384],
+            chr_rom
+        })
+    }
+
+    fn setup_rendering_ppu() -> PPU {
+        let mut ppu = setup_ppu();
+        ppu.vram.palettes[0] = 0x0F;
+        ppu.vram.palettes[1] = 0x30;
+        ppu.vram.palettes[3] = 0x16;
+        ppu.vram.palettes[5] = 0x2A;
+        ppu.vram.palettes[0x11] = 0x12;
+        ppu.vram.palettes[0x13] = 0x27;
+        ppu.vram.palettes[0x15] = 0x1A;
+        ppu.regs.ppu_mask = 0x1E;
+        ppu.oam.oam = [0xFF; 0x100];
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut PPU, n: usize, y: u8, tile: u8, attributes: u8, x: u8) {
+        ppu.oam.oam[n * 4..n * 4 + 4].copy_from_slice(&[y, tile, attributes, x]);
+    }
+
+    fn run_frames(ppu: &mut PPU, mapper: &NROM, frames: u64) {
+        let frame = ppu.frame;
+        while ppu.frame < frame + frames {
+            ppu.tick(mapper);
+        }
+    }
+
+    fn pixel(ppu: &PPU, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * 256 + x) * 3;
+        [ppu.screen[i], ppu.screen[i + 1], ppu.screen[i + 2]]
+    }
+
+    #[test]
+    fn render_background_tiles() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[0] = 0x01;
+        ppu.vram.nametables[33] = 0x02;
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 0, 0), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 7, 7), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 8, 0), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 8, 8), palette.rgb(0x16));
+        assert_eq!(pixel(&ppu, 0, 8), palette.rgb(0x0F));
+    }
+
+    #[test]
+    fn render_background_attributes() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        // The top right quadrant of the first attribute byte uses palette 1
+        ppu.vram.nametables[0] = 0x01;
+        ppu.vram.nametables[2] = 0x01;
+        ppu.vram.nametables[0x3C0] = 0x04;
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 0, 0), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 16, 0), palette.rgb(0x2A));
+    }
+
+    #[test]
+    fn render_background_fine_x() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[0] = 0x01;
+        ppu.fine_x = 3;
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 4, 0), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 5, 0), palette.rgb(0x0F));
+    }
+
+    #[test]
+    fn render_background_left_column_hidden() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[0] = 0x01;
+        ppu.regs.ppu_mask = 0x08;
+        run_frames(&mut ppu, &mapper, 2);
+
+        assert_eq!(pixel(&ppu, 0, 0), Palette::ntsc().rgb(0x0F));
+    }
+
+    #[test]
+    fn render_disabled_shows_backdrop() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[0] = 0x01;
+        ppu.vram.palettes[0] = 0x21;
+        ppu.regs.ppu_mask = 0x00;
+        run_frames(&mut ppu, &mapper, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), Palette::ntsc().rgb(0x21));
+        assert_eq!(pixel(&ppu, 255, 239), Palette::ntsc().rgb(0x21));
+    }
+
+    #[test]
+    fn render_color_emphasis() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.palettes[0] = 0x21;
+        ppu.regs.ppu_mask = 0xA0;
+        run_frames(&mut ppu, &mapper, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), Palette::ntsc().emphasized(0x21, 0x05));
+    }
+
+    #[test]
+    fn render_sprite() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        set_sprite(&mut ppu, 5, 9, 0x01, 0x01, 20);
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 20, 9), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 20, 10), palette.rgb(0x1A));
+        assert_eq!(pixel(&ppu, 27, 17), palette.rgb(0x1A));
+        assert_eq!(pixel(&ppu, 28, 17), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 27, 18), palette.rgb(0x0F));
+    }
+
+    #[test]
+    fn render_sprite_flipped() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        set_sprite(&mut ppu, 0, 9, 0x03, 0x40, 20);
+        set_sprite(&mut ppu, 1, 29, 0x04, 0x80, 20);
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 20, 10), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 27, 10), palette.rgb(0x12));
+        assert_eq!(pixel(&ppu, 20, 30), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 20, 37), palette.rgb(0x12));
+    }
+
+    #[test]
+    fn render_sprite_8x16() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.regs.ppu_ctrl = 0x20;
+        set_sprite(&mut ppu, 0, 9, 0x02, 0x00, 20);
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 21, 17), palette.rgb(0x27));
+        assert_eq!(pixel(&ppu, 20, 18), palette.rgb(0x12));
+        assert_eq!(pixel(&ppu, 21, 18), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 20, 26), palette.rgb(0x0F));
+    }
+
+    #[test]
+    fn render_sprite_priority() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[32] = 0x01;
+        ppu.vram.nametables[33] = 0x01;
+        set_sprite(&mut ppu, 0, 11, 0x02, 0x20, 8);
+        set_sprite(&mut ppu, 1, 11, 0x02, 0x00, 12);
+        set_sprite(&mut ppu, 2, 11, 0x02, 0x00, 0);
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 4, 12), palette.rgb(0x27));
+        assert_eq!(pixel(&ppu, 8, 12), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 8, 16), palette.rgb(0x27));
+        // The behind sprite comes first, so it hides the one in front too
+        assert_eq!(pixel(&ppu, 12, 12), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 16, 12), palette.rgb(0x27));
+    }
+
+    #[test]
+    fn render_sprite_limit() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        for n in 0..9 {
+            set_sprite(&mut ppu, n, 9, 0x01, 0x00, 16 * n as u8 + 8);
+        }
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 120, 10), palette.rgb(0x12));
+        assert_eq!(pixel(&ppu, 136, 10), palette.rgb(0x0F));
+    }
+
+    #[test]
+    fn sprite_zero_hit() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[33] = 0x01;
+        set_sprite(&mut ppu, 0, 7, 0x01, 0x00, 4);
+        run_frames(&mut ppu, &mapper, 1);
+        while ppu.scanline != 8 || ppu.dot != 1 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.regs.ppu_status & 0x40, 0x00);
+        while ppu.dot != 20 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.regs.ppu_status & 0x40, 0x40);
+    }
+
+    #[test]
+    fn sprite_zero_hit_needs_background() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        set_sprite(&mut ppu, 0, 7, 0x01, 0x00, 4);
+        run_frames(&mut ppu, &mapper, 1);
+        while ppu.scanline != 20 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.regs.ppu_status & 0x40, 0x00);
+    }
+
+    #[test]
+    fn sprite_zero_hit_cleared_on_pre_render_line() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[33] = 0x01;
+        set_sprite(&mut ppu, 0, 7, 0x01, 0x00, 4);
+        run_frames(&mut ppu, &mapper, 2);
+        assert_eq!(ppu.regs.ppu_status & 0x40, 0x40);
+        while ppu.scanline != 261 || ppu.dot != 2 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.regs.ppu_status & 0x40, 0x00);
+    }
+
+    #[test]
+    fn sprite_overflow() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        for n in 0..8 {
+            set_sprite(&mut ppu, n, 50, 0x01, 0x00, 0);
+        }
+        run_frames(&mut ppu, &mapper, 2);
+        assert_eq!(ppu.regs.ppu_status & 0x20, 0x00);
+
+        set_sprite(&mut ppu, 20, 52, 0x01, 0x00, 0);
+        run_frames(&mut ppu, &mapper, 1);
+        assert_eq!(ppu.regs.ppu_status & 0x20, 0x20);
+    }
+
+    #[test]
+    fn sprite_overflow_bug() {
+        let mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        for n in 0..8 {
+            set_sprite(&mut ppu, n, 50, 0x01, 0x00, 0);
+        }
+        // Off screen, but the tile number is read as a Y coordinate
+        set_sprite(&mut ppu, 9, 0xF0, 52, 0x00, 0);
+        run_frames(&mut ppu, &mapper, 2);
+        assert_eq!(ppu.regs.ppu_status & 0x20, 0x20);
+    }
+
+    #[test]
+    fn loopy_registers() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2000, 0x00, &mut mapper);
+        ppu.read_register(0x2002, &mapper);
+        ppu.write_register(0x2005, 0x7D, &mut mapper);
+        assert_eq!(ppu.t, 0x000F);
+        assert_eq!(ppu.fine_x, 0x05);
+        assert_eq!(ppu.w, true);
+        ppu.write_register(0x2005, 0x5E, &mut mapper);
+        assert_eq!(ppu.t, 0x616F);
+        assert_eq!(ppu.w, false);
+        ppu.write_register(0x2006, 0x3D, &mut mapper);
+        assert_eq!(ppu.t, 0x3D6F);
+        ppu.write_register(0x2006, 0xF0, &mut mapper);
+        assert_eq!(ppu.t, 0x3DF0);
+        assert_eq!(ppu.v, 0x3DF0);
+        ppu.write_register(0x2000, 0x03, &mut mapper);
+        assert_eq!(ppu.t, 0x3DF0 | 0x0C00);
+    }
+
+    #[test]
+    fn status_read_clears_write_toggle() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2006, 0x21, &mut mapper);
+        ppu.read_register(0x2002, &mapper);
+        ppu.write_register(0x2006, 0x23, &mut mapper);
+        ppu.write_register(0x2006, 0x45, &mut mapper);
+        assert_eq!(ppu.v, 0x2345);
+    }
+
+    #[test]
+    fn ppu_data_read_is_buffered() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.vram.nametables[0] = 0x11;
+        ppu.vram.nametables[1] = 0x22;
+        ppu.write_register(0x2006, 0x20, &mut mapper);
+        ppu.write_register(0x2006, 0x00, &mut mapper);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x00);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x11);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x22);
+    }
+
+    #[test]
+    fn ppu_data_reads_pattern_tables() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2006, 0x00, &mut mapper);
+        ppu.write_register(0x2006, 0x10, &mut mapper);
+        ppu.read_register(0x2007, &mapper);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0xFF);
+    }
+
+    #[test]
+    fn ppu_data_palette_read_skips_buffer() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.vram.palettes[0] = 0x21;
+        ppu.vram.nametables[0x700] = 0x44;
+        ppu.write_register(0x2006, 0x3F, &mut mapper);
+        ppu.write_register(0x2006, 0x00, &mut mapper);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x21);
+        assert_eq!(ppu.regs.ppu_data, 0x44);
+    }
+
+    #[test]
+    fn ppu_data_increments_by_32() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        ppu.write_register(0x2000, 0x04, &mut mapper);
+        ppu.write_register(0x2006, 0x20, &mut mapper);
+        ppu.write_register(0x2006, 0x00, &mut mapper);
+        ppu.write_register(0x2007, 0xAA, &mut mapper);
+        ppu.write_register(0x2007, 0xBB, &mut mapper);
+        assert_eq!(ppu.vram.nametables[0x00], 0xAA);
+        assert_eq!(ppu.vram.nametables[0x20], 0xBB);
+        assert_eq!(ppu.v, 0x2040);
+    }
+
+    #[test]
+    fn render_background_scrolled() {
+        let mut mapper = setup_mapper();
+        let mut ppu = setup_rendering_ppu();
+        ppu.vram.nametables[0x21] = 0x01;
+        ppu.write_register(0x2005, 0x08, &mut mapper);
+        ppu.write_register(0x2005, 0x08, &mut mapper);
+        run_frames(&mut ppu, &mapper, 2);
+
+        let palette = Palette::ntsc();
+        assert_eq!(pixel(&ppu, 0, 0), palette.rgb(0x30));
+        assert_eq!(pixel(&ppu, 8, 0), palette.rgb(0x0F));
+        assert_eq!(pixel(&ppu, 0, 8), palette.rgb(0x0F));
+    }
+
+    fn tick_to(ppu: &mut PPU, mapper: &NROM, scanline: u16, dot: u16) {
+        while ppu.scanline != scanline || ppu.dot != dot {
+            ppu.tick(mapper);
+        }
+    }
+
+    #[test]
+    fn vblank_flag_set_and_cleared() {
+        let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
+        tick_to(&mut ppu, &mapper, 241, 0);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0);
+        ppu.tick(&mapper);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0x80);
+        tick_to(&mut ppu, &mapper, 261, 0);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0x80);
+        ppu.tick(&mapper);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0);
+    }
+
+    #[test]
+    fn status_read_clears_vblank() {
+        let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
+        tick_to(&mut ppu, &mapper, 241, 1);
+        assert_eq!(ppu.read_register(0x2002, &mapper) & 0x80, 0x80);
+        assert_eq!(ppu.read_register(0x2002, &mapper) & 0x80, 0);
+    }
+
+    #[test]
+    fn status_read_suppresses_vblank() {
+        let mut ppu = setup_ppu();
+        let mapper = setup_mapper();
+        tick_to(&mut ppu, &mapper, 241, 0);
+        assert_eq!(ppu.read_register(0x2002, &mapper) & 0x80, 0);
+        ppu.tick(&mapper);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0);
+        assert_eq!(ppu.frame, 1);
+
+        // Only the frame the read raced against is affected
+        run_frames(&mut ppu, &mapper, 1);
+        assert_eq!(ppu.regs.ppu_status & 0x80, 0x80);
+    }
+
+    #[test]
+    fn nmi_output() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        tick_to(&mut ppu, &mapper, 241, 1);
+        assert_eq!(ppu.nmi(), false);
+        ppu.write_register(0x2000, 0x80, &mut mapper);
+        assert_eq!(ppu.nmi(), true);
+        ppu.read_register(0x2002, &mapper);
+        assert_eq!(ppu.nmi(), false);
+    }
+
+    fn setup_mirrored_mapper(flags_6: u8) -> NROM {
+        let mut rom = setup_mapper().get_rom().clone();
+        rom.header.flags_6 = flags_6;
+        NROM::new(rom)
+    }
+
+    fn write_vram(ppu: &mut PPU, mapper: &mut NROM, addr: u16, val: u8) {
+        ppu.write_register(0x2006, (addr >> 8) as u8, mapper);
+        ppu.write_register(0x2006, addr as u8, mapper);
+        ppu.write_register(0x2007, val, mapper);
+    }
+
+    #[test]
+    fn nametables_mirrored_horizontally() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mirrored_mapper(0);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        write_vram(&mut ppu, &mut mapper, 0x2405, 0x01);
+        write_vram(&mut ppu, &mut mapper, 0x2806, 0x02);
+        assert_eq!(ppu.vram.nametables[0x005], 0x01);
+        assert_eq!(ppu.vram.nametables[0x406], 0x02);
+    }
+
+    #[test]
+    fn nametables_mirrored_vertically() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mirrored_mapper(0x01);
+        write_vram(&mut ppu, &mut mapper, 0x2405, 0x01);
+        write_vram(&mut ppu, &mut mapper, 0x2806, 0x02);
+        assert_eq!(ppu.vram.nametables[0x405], 0x01);
+        assert_eq!(ppu.vram.nametables[0x006], 0x02);
+    }
+
+    #[test]
+    fn nametables_four_screen() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mirrored_mapper(0x08);
+        write_vram(&mut ppu, &mut mapper, 0x2805, 0x01);
+        write_vram(&mut ppu, &mut mapper, 0x2C06, 0x02);
+        assert_eq!(ppu.vram.nametables[0x805], 0x01);
+        assert_eq!(ppu.vram.nametables[0xC06], 0x02);
+    }
+
+    #[test]
+    fn nametables_mirrored_at_3000() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        write_vram(&mut ppu, &mut mapper, 0x3005, 0x01);
+        assert_eq!(ppu.vram.nametables[0x005], 0x01);
+    }
+
+    #[test]
+    fn palette_mirrors() {
+        let mut ppu = setup_ppu();
+        let mut mapper = setup_mapper();
+        write_vram(&mut ppu, &mut mapper, 0x3F10, 0x01);
+        write_vram(&mut ppu, &mut mapper, 0x3F1C, 0x02);
+        write_vram(&mut ppu, &mut mapper, 0x3F11, 0x03);
+        write_vram(&mut ppu, &mut mapper, 0x3F25, 0x04);
+        assert_eq!(ppu.vram.palettes[0x00], 0x01);
+        assert_eq!(ppu.vram.palettes[0x0C], 0x02);
+        assert_eq!(ppu.vram.palettes[0x11], 0x03);
+        assert_eq!(ppu.vram.palettes[0x05], 0x04);
+        assert_eq!(ppu.vram.palettes[0x10], 0x00);
+    }
+
+    #[test]
+    fn pattern_tables_written_to_mapper() {
+        let mut ppu = setup_ppu();
+        let mut rom = setup_mapper().get_rom().clone();
+        rom.chr_rom = vec![];
+        let mut mapper = NROM::new(rom);
+        write_vram(&mut ppu, &mut mapper, 0x1234, 0xAB);
+        assert_eq!(mapper.load_chr_byte(0x1234), 0xAB);
     }
-    
 }
@@ -0,0 +1,30 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod rom_tests {
+    use mr_cool_nes::core::error::EmuError;
+    use mr_cool_nes::core::rom::Rom;
+
+    #[test]
+    fn load_rom() {
+        let rom = Rom::load(&"tests/roms/Galaxian.nes".to_owned()).unwrap();
+        assert_eq!(rom.header.mapper_number(), 0);
+        assert_eq!(rom.prg_rom.len(), rom.header.prg_rom_size as usize * 0x4000);
+    }
+
+    #[test]
+    fn load_missing_rom() {
+        match Rom::load(&"tests/roms/missing.nes".to_owned()) {
+            Err(EmuError::Io(_)) => (),
+            _ => panic!("Expected an I/O error")
+        }
+    }
+
+    #[test]
+    fn load_invalid_rom() {
+        match Rom::load(&"tests/tests.md".to_owned()) {
+            Err(EmuError::InvalidRom(_)) => (),
+            _ => panic!("Expected an invalid rom error")
+        }
+    }
+}
@@ -0,0 +1,162 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod scheduler_tests {
+    use mr_cool_nes::core::cpu::CPU;
+    use mr_cool_nes::core::mapper::{NROM, SxROM};
+    use mr_cool_nes::core::memory::{Memory, RAM};
+    use mr_cool_nes::core::ppu::PPU;
+    use mr_cool_nes::core::rom::{INesHeader, Rom};
+    use mr_cool_nes::core::scheduler::{Region, Scheduler};
+
+    fn setup_rom() -> Rom {
+        Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom: vec![0; 16384],
+            chr_rom: vec![0; 8192]
+        }
+    }
+
+    fn setup_cpu(program: &[u8]) -> CPU {
+        let mut cpu = CPU::new(PPU::new(), RAM::new(), Box::new(NROM::new(setup_rom())));
+        cpu.mem_map.ram.mem[0x200..0x200 + program.len()].copy_from_slice(program);
+        cpu.regs.pc = 0x200;
+        cpu
+    }
+
+    #[test]
+    fn ntsc_three_dots_per_cycle() {
+        let mut scheduler = Scheduler::new(Region::NTSC);
+        for _ in 0..10 {
+            scheduler.advance();
+        }
+        assert_eq!(scheduler.take_ppu_dots(), 30);
+        assert_eq!(scheduler.take_ppu_dots(), 0);
+        assert_eq!(scheduler.take_cpu_cycles(), 10);
+    }
+
+    #[test]
+    fn pal_sixteen_dots_per_five_cycles() {
+        let mut scheduler = Scheduler::new(Region::PAL);
+        for _ in 0..5 {
+            scheduler.advance();
+        }
+        assert_eq!(scheduler.take_ppu_dots(), 16);
+        scheduler.advance();
+        assert_eq!(scheduler.take_ppu_dots(), 3);
+        scheduler.advance();
+        assert_eq!(scheduler.take_ppu_dots(), 3);
+        scheduler.advance();
+        assert_eq!(scheduler.take_ppu_dots(), 3);
+        scheduler.advance();
+        assert_eq!(scheduler.take_ppu_dots(), 3);
+        scheduler.advance();
+        assert_eq!(scheduler.take_ppu_dots(), 4);
+    }
+
+    #[test]
+    fn ppu_follows_cpu_instructions() {
+        let mut cpu = setup_cpu(&[0xEA, 0xAD, 0x02, 0x20, 0xEE, 0x00, 0x03]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycles, 12);
+        assert_eq!(cpu.mem_map.ppu.dot, 36);
+        assert_eq!(cpu.mem_map.apu.cycles, 12);
+    }
+
+    #[test]
+    fn ppu_catches_up_on_register_access() {
+        let mut cpu = setup_cpu(&[]);
+        cpu.load_byte(0x8000);
+        cpu.load_byte(0x0000);
+        assert_eq!(cpu.mem_map.ppu.dot, 0);
+        cpu.load_byte(0x2002);
+        assert_eq!(cpu.mem_map.ppu.dot, 9);
+        cpu.load_byte(0x8000);
+        cpu.store_byte(0x8000, 0x00);
+        assert_eq!(cpu.mem_map.ppu.dot, 15);
+    }
+
+    #[test]
+    fn ppu_runs_during_dma_stall() {
+        let mut cpu = setup_cpu(&[0xA9, 0x03, 0x8D, 0x14, 0x40]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        while cpu.stall_cycles() > 0 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.cycles, 519);
+        assert_eq!(cpu.mem_map.ppu.scanline as u64 * 341 + cpu.mem_map.ppu.dot as u64, 519 * 3);
+    }
+
+    #[test]
+    fn ppu_frame_wraps() {
+        let mapper = NROM::new(setup_rom());
+        let mut ppu = PPU::new();
+        for _ in 0..341 * 262 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.scanline, 0);
+        assert_eq!(ppu.dot, 0);
+        assert_eq!(ppu.frame, 1);
+    }
+
+    #[test]
+    fn odd_frame_skips_a_dot_while_rendering() {
+        let mapper = NROM::new(setup_rom());
+        let mut ppu = PPU::new();
+        ppu.regs.ppu_mask = 0x08;
+        for _ in 0..341 * 262 * 2 - 1 {
+            ppu.tick(&mapper);
+        }
+        assert_eq!(ppu.scanline, 0);
+        assert_eq!(ppu.dot, 0);
+    }
+
+    #[test]
+    fn ppu_follows_mapper_mirroring() {
+        let mut rom = setup_rom();
+        rom.header.flags_6 = 0x10;
+        let mut cpu = CPU::new(PPU::new(), RAM::new(), Box::new(SxROM::new(rom)));
+        let program = [
+            // $2405 = $AA while single screen
+            0xA9, 0x24, 0x8D, 0x06, 0x20, 0xA9, 0x05, 0x8D, 0x06, 0x20,
+            0xA9, 0xAA, 0x8D, 0x07, 0x20,
+            // Wait for vblank and switch to vertical mirroring
+            0x2C, 0x02, 0x20, 0x10, 0xFB,
+            0xA9, 0x00, 0x8D, 0x00, 0x80, 0xA9, 0x01, 0x8D, 0x00, 0x80,
+            0xA9, 0x00, 0x8D, 0x00, 0x80, 0x8D, 0x00, 0x80, 0x8D, 0x00, 0x80,
+            // $2405 = $55
+            0xA9, 0x24, 0x8D, 0x06, 0x20, 0xA9, 0x05, 0x8D, 0x06, 0x20,
+            0xA9, 0x55, 0x8D, 0x07, 0x20,
+            // $10 = $2C05, $11 = $2005
+            0xA9, 0x2C, 0x8D, 0x06, 0x20, 0xA9, 0x05, 0x8D, 0x06, 0x20,
+            0xAD, 0x07, 0x20, 0xAD, 0x07, 0x20, 0x85, 0x10,
+            0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x05, 0x8D, 0x06, 0x20,
+            0xAD, 0x07, 0x20, 0xAD, 0x07, 0x20, 0x85, 0x11,
+            0x4C, 0x5C, 0x02
+        ];
+        cpu.mem_map.ram.mem[0x200..0x200 + program.len()].copy_from_slice(&program);
+        cpu.regs.pc = 0x200;
+
+        while cpu.regs.pc != 0x25C {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.mem_map.ppu.frame, 1);
+        assert_eq!(cpu.mem_map.ppu.vram.nametables[0x005], 0xAA);
+        assert_eq!(cpu.mem_map.ppu.vram.nametables[0x405], 0x55);
+        assert_eq!(cpu.mem_map.ram.mem[0x10], 0x55);
+        assert_eq!(cpu.mem_map.ram.mem[0x11], 0xAA);
+    }
+}
@@ -0,0 +1,114 @@
+extern crate mr_cool_nes;
+
+#[cfg(test)]
+mod trace_tests {
+    use mr_cool_nes::core::cpu::CPU;
+    use mr_cool_nes::core::ppu::PPU;
+    use mr_cool_nes::core::memory::{Memory, RAM};
+    use mr_cool_nes::core::rom::{INesHeader, Rom};
+    use mr_cool_nes::core::mapper::TestMapper;
+    use mr_cool_nes::core::trace::trace_line;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    fn setup_rom() -> Rom {
+        Rom {
+            header: INesHeader {
+                magic: *b"NES\x1a",
+                prg_rom_size: 1,
+                chr_rom_size: 1,
+                flags_6: 0,
+                flags_7: 0,
+                prg_ram_size: 1,
+                flags_9: 0,
+                flags_10: 0,
+                zero: [0; 5]
+            },
+            prg_rom: vec![0; 16384],
+            chr_rom: vec![0; 16384]
+        }
+    }
+
+    // Same starting state as the nestest reference log
+    fn setup_cpu(program: &[u8]) -> CPU {
+        let mut cpu = CPU::new(PPU::new(), RAM::new(), Box::new(TestMapper::new(setup_rom())));
+        for (i, byte) in program.iter().enumerate() {
+            cpu.mem_map.store_byte(0xC000 + i as u16, *byte);
+        }
+        cpu.mem_map.store_byte(0xFFFC, 0x00);
+        cpu.mem_map.store_byte(0xFFFD, 0xC0);
+        cpu.reset();
+        cpu.regs.p = 0x24;
+        cpu.regs.s = 0xFD;
+        cpu
+    }
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn trace_jmp() {
+        let cpu = setup_cpu(&[0x4C, 0xF5, 0xC5]);
+        assert_eq!(trace_line(&cpu),
+                   "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7");
+    }
+
+    #[test]
+    fn trace_immediate() {
+        let mut cpu = setup_cpu(&[0x4C, 0x03, 0xC0, 0xA2, 0x00]);
+        cpu.step().unwrap();
+        assert_eq!(trace_line(&cpu),
+                   "C003  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10");
+    }
+
+    #[test]
+    fn trace_unofficial() {
+        let mut cpu = setup_cpu(&[0x04, 0xA9]);
+        cpu.store_byte(0xA9, 0x00);
+        assert!(trace_line(&cpu).starts_with("C000  04 A9    *NOP $A9 = 00                    A:00"));
+    }
+
+    #[test]
+    fn trace_indirect_indexed() {
+        let mut cpu = setup_cpu(&[0xB1, 0x89]);
+        cpu.store_byte(0x89, 0x00);
+        cpu.store_byte(0x8A, 0x03);
+        cpu.store_byte(0x0334, 0x55);
+        cpu.regs.y = 0x34;
+        assert!(trace_line(&cpu).starts_with("C000  B1 89     LDA ($89),Y = 0300 @ 0334 = 55  A:00"));
+    }
+
+    #[test]
+    fn trace_sink_toggle() {
+        let mut cpu = setup_cpu(&[0xEA, 0xEA, 0xEA]);
+        let buffer = SharedBuffer(Arc::new(Mutex::new(Vec::new())));
+        cpu.trace_to(Box::new(buffer.clone()));
+        cpu.step().unwrap();
+        cpu.set_tracing(false);
+        cpu.step().unwrap();
+        cpu.set_tracing(true);
+        cpu.step().unwrap();
+
+        let output = buffer.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("C000  EA        NOP"));
+        assert!(lines[1].starts_with("C002  EA        NOP"));
+    }
+}